@@ -0,0 +1,714 @@
+extern crate socket2;
+
+use std::collections::{ HashMap, HashSet };
+use std::io::{ self, ErrorKind, Write };
+use std::net::{ IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream, UdpSocket };
+use std::sync::{ Arc, Mutex };
+use std::thread;
+use std::time::{ Duration, Instant };
+
+use crate::attributes::AttributeBody;
+use crate::attributes::connection_id::ConnectionId;
+use crate::attributes::data::Data;
+use crate::attributes::error_code::ErrorCodeType;
+use crate::attributes::xor_mapped_address;
+use crate::attributes::requested_transport;
+use crate::attributes::xor_peer_address::XorPeerAddress;
+use crate::channel_data;
+use crate::config::IpRange;
+use crate::framing::TimedRead;
+use crate::handlers;
+use crate::header::MessageType;
+use crate::transport::{ Connection, FiveTuple, Transport };
+
+use socket2::{ Domain, Protocol, Socket, Type };
+
+// Allocation lifetimes in seconds (RFC 5766 section 2.2)
+const DEFAULT_LIFETIME: u32 = 600;
+const MAX_LIFETIME: u32 = 3600;
+// Permissions cannot be given a lifetime by the client (RFC 5766 section 8)
+const PERMISSION_LIFETIME: u64 = 300;
+const CHANNEL_LIFETIME: u64 = 600;
+// Peer connections not bound to a data connection in time are closed (RFC 6062 section 5.3)
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(30);
+// How often TCP relays check for new peer connections and for their allocation going away
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+
+// UDP allocations relay datagrams, while TCP allocations (RFC 6062) accept
+// and open a TCP connection per peer
+enum Relay {
+    Udp(Arc<UdpSocket>),
+    Tcp(Arc<TcpListener>),
+}
+impl Relay {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            Relay::Udp(socket) => socket.local_addr(),
+            Relay::Tcp(listener) => listener.local_addr(),
+        }
+    }
+}
+
+// A client connection, over TCP or TLS, that carries peer data once a ConnectionBind has
+// succeeded on it
+pub trait DataConnection: TimedRead + Write + Send + Sized + 'static {
+    // Another handle on the same connection, so that each direction can be relayed by a thread of its own
+    fn try_clone(&self) -> io::Result<Self>;
+    // Ends the connection both ways, waking whatever is reading from it
+    fn close(&self);
+}
+impl DataConnection for TcpStream {
+    fn try_clone(&self) -> io::Result<TcpStream> {
+        TcpStream::try_clone(self)
+    }
+
+    fn close(&self) {
+        let _ = self.shutdown(Shutdown::Both);
+    }
+}
+
+pub struct Allocation {
+    relay: Relay,
+    connection: Connection,
+    // Whoever made the allocation, who alone may use it (RFC 5766 section 6.2)
+    username: String,
+    expiry: Instant,
+    // Lets a retransmitted Allocate be answered instead of rejected
+    transaction_id: [u8; 12],
+    permissions: HashMap<IpAddr, Instant>,
+    channels: HashMap<u16, (SocketAddr, Instant)>,
+}
+impl Allocation {
+    fn permits(&self, peer:&IpAddr) -> bool {
+        match self.permissions.get(peer) {
+            Some(expiry) => *expiry > Instant::now(),
+            None => false,
+        }
+    }
+
+    fn channel_peer(&self, channel:u16) -> Option<SocketAddr> {
+        match self.channels.get(&channel) {
+            Some((peer, expiry)) if *expiry > Instant::now() => Some(*peer),
+            _ => None,
+        }
+    }
+
+    fn peer_channel(&self, peer:&SocketAddr) -> Option<u16> {
+        let now = Instant::now();
+        self.channels.iter()
+                     .find(|(_, (channel_peer, expiry))| channel_peer == peer && *expiry > now)
+                     .map(|(channel, _)| *channel)
+    }
+}
+
+type AllocationTable = Arc<Mutex<HashMap<FiveTuple, Allocation>>>;
+
+// A TCP connection between a TCP allocation and one of its peers
+struct PeerConnection {
+    // The allocation the connection belongs to
+    tuple: FiveTuple,
+    peer: SocketAddr,
+    stream: TcpStream,
+    // The user who made the allocation, who alone may bind a data connection to it
+    username: String,
+    // Set once the client has bound a data connection to it
+    bound: bool,
+    created: Instant,
+}
+
+// Peer connections keyed by CONNECTION-ID
+type ConnectionTable = Arc<Mutex<HashMap<u32, PeerConnection>>>;
+
+pub struct Allocations {
+    relay_ip: IpAddr,
+    // Peers no permission, channel or connection may be made to
+    denied_peers: Vec<IpRange>,
+    // Most allocations one user, and everyone together, may hold at once
+    user_quota: Option<usize>,
+    total_quota: Option<usize>,
+    allocations: AllocationTable,
+    connections: ConnectionTable,
+    // Data connections whose ConnectionBind has succeeded, waiting to start relaying
+    data_connections: Mutex<HashMap<FiveTuple, (u32, TcpStream)>>,
+}
+impl Allocations {
+    pub fn new(relay_ip:IpAddr, denied_peers:Vec<IpRange>, user_quota:Option<usize>, total_quota:Option<usize>) -> Allocations {
+        Allocations {
+            relay_ip,
+            denied_peers,
+            user_quota,
+            total_quota,
+            allocations: Arc::new(Mutex::new(HashMap::new())),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            data_connections: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Returns the relayed transport address and the granted lifetime
+    pub fn allocate(&self, connection:&Connection, transaction_id:&[u8; 12], protocol:u8, lifetime:Option<u32>, username:&str) -> Result<(SocketAddr, u32), ErrorCodeType> {
+        let tuple = connection.tuple;
+        let mut allocations = match self.allocations.lock() {
+            Ok(allocations) => allocations,
+            Err(_) => return Err(ErrorCodeType::ServerError),
+        };
+
+        let now = Instant::now();
+        if let Some(allocation) = allocations.get(&tuple) {
+            if allocation.expiry > now {
+                if &allocation.transaction_id != transaction_id {
+                    return Err(ErrorCodeType::AllocationMismatch);
+                }
+                let relayed_address = match allocation.relay.local_addr() {
+                    Ok(relayed_address) => relayed_address,
+                    Err(_) => return Err(ErrorCodeType::ServerError),
+                };
+                let remaining = allocation.expiry.duration_since(now).as_secs() as u32;
+                return Ok((relayed_address, remaining));
+            }
+        }
+
+        // Only unexpired allocations count towards the quotas (RFC 5766 section 6.2)
+        let (total, held) = allocations.values()
+                                       .filter(|allocation| allocation.expiry > now)
+                                       .fold((0, 0), |(total, held), allocation| (total + 1, held + (allocation.username == username) as usize));
+        if self.user_quota.is_some_and(|quota| held >= quota) || self.total_quota.is_some_and(|quota| total >= quota) {
+            return Err(ErrorCodeType::AllocationQuotaReached);
+        }
+
+        let relay = match protocol {
+            requested_transport::TCP => match listen(SocketAddr::new(self.relay_ip, 0)) {
+                Ok(listener) => Relay::Tcp(Arc::new(listener)),
+                Err(_) => return Err(ErrorCodeType::InsufficientCapacity),
+            },
+            _ => match UdpSocket::bind(SocketAddr::new(self.relay_ip, 0)) {
+                // The relay thread wakes periodically to notice that the allocation is gone
+                Ok(socket) => match socket.set_read_timeout(Some(Duration::from_secs(1))) {
+                    Ok(_) => Relay::Udp(Arc::new(socket)),
+                    Err(_) => return Err(ErrorCodeType::InsufficientCapacity),
+                },
+                Err(_) => return Err(ErrorCodeType::InsufficientCapacity),
+            },
+        };
+        let relayed_address = match relay.local_addr() {
+            Ok(relayed_address) => relayed_address,
+            Err(_) => return Err(ErrorCodeType::InsufficientCapacity),
+        };
+
+        let lifetime = match lifetime {
+            Some(lifetime) => lifetime.clamp(DEFAULT_LIFETIME, MAX_LIFETIME),
+            None => DEFAULT_LIFETIME,
+        };
+        let table = Arc::clone(&self.allocations);
+        match &relay {
+            Relay::Udp(socket) => {
+                let socket = Arc::clone(socket);
+                thread::spawn(move || relay_from_peers(table, tuple, socket));
+            },
+            Relay::Tcp(listener) => {
+                let listener = Arc::clone(listener);
+                let connections = Arc::clone(&self.connections);
+                thread::spawn(move || accept_peers(table, connections, tuple, listener));
+            },
+        }
+
+        let allocation = Allocation {
+            relay,
+            connection: connection.clone(),
+            username: String::from(username),
+            expiry: now + Duration::from_secs(lifetime as u64),
+            transaction_id: *transaction_id,
+            permissions: HashMap::new(),
+            channels: HashMap::new(),
+        };
+        allocations.insert(tuple, allocation);
+
+        Ok((relayed_address, lifetime))
+    }
+
+    // Returns the granted lifetime; a lifetime of zero deletes the allocation
+    pub fn refresh(&self, tuple:&FiveTuple, lifetime:Option<u32>, username:&str) -> Result<u32, ErrorCodeType> {
+        let mut allocations = match self.allocations.lock() {
+            Ok(allocations) => allocations,
+            Err(_) => return Err(ErrorCodeType::ServerError),
+        };
+
+        let now = Instant::now();
+        let allocation = owned_allocation(&mut allocations, tuple, username)?;
+
+        let lifetime = match lifetime {
+            Some(lifetime) => lifetime.min(MAX_LIFETIME),
+            None => DEFAULT_LIFETIME,
+        };
+        if lifetime == 0 {
+            allocations.remove(tuple);
+        } else {
+            allocation.expiry = now + Duration::from_secs(lifetime as u64);
+        }
+
+        Ok(lifetime)
+    }
+
+    pub fn has_allocation(&self, tuple:&FiveTuple) -> bool {
+        match self.allocations.lock() {
+            Ok(allocations) => match allocations.get(tuple) {
+                Some(allocation) => allocation.expiry > Instant::now(),
+                None => false,
+            },
+            Err(_) => false,
+        }
+    }
+
+    // Deletes the allocation, if any, when the client's connection goes away
+    pub fn release(&self, tuple:&FiveTuple) {
+        if let Ok(mut allocations) = self.allocations.lock() {
+            allocations.remove(tuple);
+        }
+        if let Ok(mut data_connections) = self.data_connections.lock() {
+            data_connections.remove(tuple);
+        }
+    }
+
+    // Installs or refreshes a permission for each peer's IP address, ignoring their ports
+    pub fn permit(&self, tuple:&FiveTuple, peers:&[IpAddr], username:&str) -> Result<(), ErrorCodeType> {
+        if peers.iter().any(|peer| self.denied(peer)) {
+            return Err(ErrorCodeType::Forbidden);
+        }
+
+        let mut allocations = match self.allocations.lock() {
+            Ok(allocations) => allocations,
+            Err(_) => return Err(ErrorCodeType::ServerError),
+        };
+
+        let now = Instant::now();
+        let allocation = owned_allocation(&mut allocations, tuple, username)?;
+        for peer in peers {
+            allocation.permissions.insert(*peer, now + Duration::from_secs(PERMISSION_LIFETIME));
+        }
+
+        Ok(())
+    }
+
+    // Binds or refreshes a channel, which also installs or refreshes a permission for the peer
+    pub fn bind_channel(&self, tuple:&FiveTuple, channel:u16, peer:&SocketAddr, username:&str) -> Result<(), ErrorCodeType> {
+        if !(channel_data::MIN_CHANNEL..=channel_data::MAX_CHANNEL).contains(&channel) {
+            return Err(ErrorCodeType::BadRequest);
+        }
+        if self.denied(&peer.ip()) {
+            return Err(ErrorCodeType::Forbidden);
+        }
+
+        let mut allocations = match self.allocations.lock() {
+            Ok(allocations) => allocations,
+            Err(_) => return Err(ErrorCodeType::ServerError),
+        };
+
+        let now = Instant::now();
+        let allocation = owned_allocation(&mut allocations, tuple, username)?;
+
+        // A channel is bound to at most one peer and a peer to at most one channel
+        let bound_peer = allocation.channel_peer(channel);
+        let bound_channel = allocation.peer_channel(peer);
+        if bound_peer.is_some() && bound_peer != Some(*peer) {
+            return Err(ErrorCodeType::BadRequest);
+        }
+        if bound_channel.is_some() && bound_channel != Some(channel) {
+            return Err(ErrorCodeType::BadRequest);
+        }
+
+        allocation.channels.insert(channel, (*peer, now + Duration::from_secs(CHANNEL_LIFETIME)));
+        allocation.permissions.insert(peer.ip(), now + Duration::from_secs(PERMISSION_LIFETIME));
+
+        Ok(())
+    }
+
+    // Relays ChannelData from the client to the peer bound to the channel
+    pub fn send_channel_data(&self, tuple:&FiveTuple, channel:u16, data:&[u8]) {
+        let (relay, peer) = match self.allocations.lock() {
+            Ok(allocations) => match allocations.get(tuple) {
+                Some(allocation) => match (&allocation.relay, allocation.channel_peer(channel)) {
+                    (Relay::Udp(socket), Some(peer)) if allocation.permits(&peer.ip()) => (Arc::clone(socket), peer),
+                    _ => return,
+                },
+                None => return,
+            },
+            Err(_) => return,
+        };
+
+        let _ = relay.send_to(data, peer);
+    }
+
+    // Relays data from the client to a peer; silently dropped without a permission
+    pub fn send(&self, tuple:&FiveTuple, peer:&SocketAddr, data:&[u8]) {
+        let relay = match self.allocations.lock() {
+            Ok(allocations) => match allocations.get(tuple) {
+                Some(allocation) => match &allocation.relay {
+                    Relay::Udp(socket) if allocation.permits(&peer.ip()) => Arc::clone(socket),
+                    _ => return,
+                },
+                None => return,
+            },
+            Err(_) => return,
+        };
+
+        let _ = relay.send_to(data, peer);
+    }
+
+    // Opens a TCP connection from the relayed address to the peer, returning its CONNECTION-ID
+    pub fn connect(&self, tuple:&FiveTuple, peer:&SocketAddr, username:&str) -> Result<u32, ErrorCodeType> {
+        if self.denied(&peer.ip()) {
+            return Err(ErrorCodeType::Forbidden);
+        }
+        let relayed_address = match self.allocations.lock() {
+            Ok(mut allocations) => {
+                let allocation = owned_allocation(&mut allocations, tuple, username)?;
+                match &allocation.relay {
+                    Relay::Tcp(listener) if allocation.permits(&peer.ip()) => listener.local_addr(),
+                    Relay::Tcp(_) => return Err(ErrorCodeType::Forbidden),
+                    Relay::Udp(_) => return Err(ErrorCodeType::BadRequest),
+                }
+            },
+            Err(_) => return Err(ErrorCodeType::ServerError),
+        };
+        let relayed_address = match relayed_address {
+            Ok(relayed_address) => relayed_address,
+            Err(_) => return Err(ErrorCodeType::ServerError),
+        };
+
+        let connected = match self.connections.lock() {
+            Ok(connections) => connections.values().any(|connection| connection.tuple == *tuple && connection.peer == *peer),
+            Err(_) => return Err(ErrorCodeType::ServerError),
+        };
+        if connected {
+            return Err(ErrorCodeType::ConnectionAlreadyExists);
+        }
+
+        let stream = match connect(relayed_address, peer) {
+            Ok(stream) => stream,
+            Err(_) => return Err(ErrorCodeType::ConnectionTimeoutOrFailure),
+        };
+        match add_connection(&self.connections, *tuple, *peer, stream, username) {
+            Some(id) => Ok(id),
+            None => Err(ErrorCodeType::ServerError),
+        }
+    }
+
+    // Binds a new client data connection to a peer connection; relaying starts once the
+    // response has been sent, by way of relay_connection
+    pub fn bind_connection(&self, tuple:&FiveTuple, id:u32, username:&str) -> Result<(), ErrorCodeType> {
+        // A data connection cannot also be a control connection
+        match self.allocations.lock() {
+            Ok(allocations) if allocations.contains_key(tuple) => return Err(ErrorCodeType::BadRequest),
+            Ok(_) => (),
+            Err(_) => return Err(ErrorCodeType::ServerError),
+        }
+
+        let stream = match self.connections.lock() {
+            Ok(mut connections) => match connections.get_mut(&id) {
+                Some(connection) if !connection.bound && connection.created.elapsed() < CONNECTION_TIMEOUT => match connection.username == username {
+                    true => {
+                        connection.bound = true;
+                        connection.stream.try_clone()
+                    },
+                    false => return Err(ErrorCodeType::WrongCredentials),
+                },
+                _ => return Err(ErrorCodeType::BadRequest),
+            },
+            Err(_) => return Err(ErrorCodeType::ServerError),
+        };
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => return Err(ErrorCodeType::ServerError),
+        };
+
+        match self.data_connections.lock() {
+            Ok(mut data_connections) => match data_connections.contains_key(tuple) {
+                true => Err(ErrorCodeType::BadRequest),
+                false => {
+                    data_connections.insert(*tuple, (id, stream));
+                    Ok(())
+                },
+            },
+            Err(_) => Err(ErrorCodeType::ServerError),
+        }
+    }
+
+    // Relays raw data between a bound data connection and its peer until either side
+    // closes; returns false, leaving the stream alone, if the connection is not bound
+    pub fn relay_connection<C: DataConnection>(&self, tuple:&FiveTuple, client:&C) -> bool {
+        let (id, peer) = match self.data_connections.lock() {
+            Ok(mut data_connections) => match data_connections.remove(tuple) {
+                Some(data_connection) => data_connection,
+                None => return false,
+            },
+            Err(_) => return false,
+        };
+
+        if let (Ok(mut client_reader), Ok(client_writer), Ok(peer_writer)) = (client.try_clone(), client.try_clone(), peer.try_clone()) {
+            // Relayed data can go quiet for as long as the application likes
+            let _ = client_reader.set_read_timeout(None);
+            let upstream = thread::spawn(move || copy_stream(client_reader, peer_writer));
+            copy_stream(peer, client_writer);
+            let _ = upstream.join();
+        }
+
+        if let Ok(mut connections) = self.connections.lock() {
+            if let Some(connection) = connections.remove(&id) {
+                let _ = connection.stream.shutdown(Shutdown::Both);
+            }
+        }
+
+        true
+    }
+
+    fn denied(&self, peer:&IpAddr) -> bool {
+        self.denied_peers.iter().any(|range| range.contains(peer))
+    }
+
+    // Drops expired allocations, closing their relay sockets and peer connections,
+    // expired permissions and channels, and peer connections never bound in time
+    pub fn expire(&self) {
+        let live = match self.allocations.lock() {
+            Ok(mut allocations) => {
+                let now = Instant::now();
+                allocations.retain(|_, allocation| allocation.expiry > now);
+                for allocation in allocations.values_mut() {
+                    allocation.permissions.retain(|_, expiry| *expiry > now);
+                    allocation.channels.retain(|_, (_, expiry)| *expiry > now);
+                }
+                allocations.keys().copied().collect::<HashSet<FiveTuple>>()
+            },
+            Err(_) => return,
+        };
+
+        if let Ok(mut connections) = self.connections.lock() {
+            connections.retain(|_, connection| {
+                let keep = live.contains(&connection.tuple) && (connection.bound || connection.created.elapsed() < CONNECTION_TIMEOUT);
+                if !keep {
+                    // Also ends the relaying of a bound connection
+                    let _ = connection.stream.shutdown(Shutdown::Both);
+                }
+                keep
+            });
+        }
+    }
+}
+
+// The unexpired allocation for the 5-tuple, provided the request came from the user who made it
+fn owned_allocation<'a>(allocations:&'a mut HashMap<FiveTuple, Allocation>, tuple:&FiveTuple, username:&str) -> Result<&'a mut Allocation, ErrorCodeType> {
+    match allocations.get_mut(tuple) {
+        Some(allocation) if allocation.expiry > Instant::now() => match allocation.username == username {
+            true => Ok(allocation),
+            false => Err(ErrorCodeType::WrongCredentials),
+        },
+        _ => Err(ErrorCodeType::AllocationMismatch),
+    }
+}
+
+// Peer connections are opened from the relayed address itself, which the relay's listener
+// is also bound to, so both sockets need address and port reuse
+fn reusable_socket(address:&SocketAddr) -> io::Result<Socket> {
+    let socket = Socket::new(Domain::for_address(*address), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    Ok(socket)
+}
+
+fn listen(address:SocketAddr) -> io::Result<TcpListener> {
+    let socket = reusable_socket(&address)?;
+    socket.bind(&address.into())?;
+    socket.listen(128)?;
+    // The accept thread polls so that it can notice that the allocation is gone
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
+
+fn connect(relayed_address:SocketAddr, peer:&SocketAddr) -> io::Result<TcpStream> {
+    let socket = reusable_socket(peer)?;
+    socket.bind(&relayed_address.into())?;
+    socket.connect_timeout(&(*peer).into(), CONNECTION_TIMEOUT)?;
+    Ok(socket.into())
+}
+
+fn add_connection(connections:&ConnectionTable, tuple:FiveTuple, peer:SocketAddr, stream:TcpStream, username:&str) -> Option<u32> {
+    let mut connections = connections.lock().ok()?;
+    let mut id = rand::random::<u32>();
+    while connections.contains_key(&id) {
+        id = rand::random::<u32>();
+    }
+    connections.insert(id, PeerConnection {
+        tuple,
+        peer,
+        stream,
+        username: String::from(username),
+        bound: false,
+        created: Instant::now(),
+    });
+    Some(id)
+}
+
+// Either side closing ends the relaying in both directions (RFC 6062 section 5.5)
+fn copy_stream<F: DataConnection, T: DataConnection>(mut from:F, mut to:T) {
+    let _ = io::copy(&mut from, &mut to);
+    from.close();
+    to.close();
+}
+
+// Accepts peer connections on a TCP relay, offering those with a permission to the client
+// with a ConnectionAttempt indication and closing the rest
+fn accept_peers(table:AllocationTable, connections:ConnectionTable, tuple:FiveTuple, relay:Arc<TcpListener>) {
+    loop {
+        let accepted = relay.accept();
+
+        let connection = match table.lock() {
+            Ok(allocations) => match allocations.get(&tuple) {
+                Some(allocation) => match (&allocation.relay, &accepted) {
+                    // The allocation may have been replaced by a new one with its own relay
+                    (Relay::Tcp(listener), _) if !Arc::ptr_eq(listener, &relay) => return,
+                    (Relay::Udp(_), _) => return,
+                    (_, Ok((_, peer))) if allocation.permits(&peer.ip()) => Some((allocation.connection.clone(), allocation.username.clone())),
+                    _ => None,
+                },
+                None => return,
+            },
+            Err(_) => return,
+        };
+
+        let (stream, peer) = match accepted {
+            Ok(accepted) => accepted,
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(ACCEPT_INTERVAL);
+                continue;
+            },
+            Err(_) => return,
+        };
+        let (connection, username) = match connection {
+            Some(connection) => connection,
+            None => continue,
+        };
+        if stream.set_nonblocking(false).is_err() {
+            continue;
+        }
+        let connection_id = match add_connection(&connections, tuple, peer, stream, &username) {
+            Some(connection_id) => connection_id,
+            None => continue,
+        };
+
+        let id = rand::random::<[u8; 12]>();
+        let key = xor_mapped_address::address_key(&peer, &id);
+        let peer_address = match XorPeerAddress::with_address(peer, key) {
+            Ok(peer_address) => peer_address,
+            Err(_) => continue,
+        };
+        let body = vec![
+            AttributeBody::XorPeerAddress(peer_address),
+            AttributeBody::ConnectionId(ConnectionId::with_value(connection_id)),
+        ];
+
+        let _ = connection.send(&handlers::build_indication(MessageType::ConnectionAttempt, &id, body));
+    }
+}
+
+// Forwards datagrams arriving on the relay to the client, as ChannelData if the
+// peer is bound to a channel and as Data indications otherwise
+fn relay_from_peers(table:AllocationTable, tuple:FiveTuple, relay:Arc<UdpSocket>) {
+    let mut buf = vec![0; 65536];
+    loop {
+        let received = relay.recv_from(&mut buf);
+
+        let connection = match table.lock() {
+            Ok(allocations) => match allocations.get(&tuple) {
+                Some(allocation) => match (&allocation.relay, &received) {
+                    // The allocation may have been replaced by a new one with its own relay
+                    (Relay::Udp(socket), _) if !Arc::ptr_eq(socket, &relay) => return,
+                    (Relay::Tcp(_), _) => return,
+                    (_, Ok((_, peer))) if allocation.permits(&peer.ip()) => Some((allocation.connection.clone(), allocation.peer_channel(peer))),
+                    _ => None,
+                },
+                None => return,
+            },
+            Err(_) => return,
+        };
+
+        let (amt, peer) = match received {
+            Ok(received) => received,
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => continue,
+            Err(_) => return,
+        };
+        let (connection, channel) = match connection {
+            Some(connection) => connection,
+            None => continue,
+        };
+
+        if let Some(channel) = channel {
+            let pad = matches!(tuple.transport, Transport::Tcp | Transport::Tls);
+            let _ = connection.send(&channel_data::serialise(channel, &buf[0..amt], pad));
+            continue;
+        }
+
+        let id = rand::random::<[u8; 12]>();
+        let key = xor_mapped_address::address_key(&peer, &id);
+        let peer_address = match XorPeerAddress::with_address(peer, key) {
+            Ok(peer_address) => peer_address,
+            Err(_) => continue,
+        };
+        let body = vec![
+            AttributeBody::XorPeerAddress(peer_address),
+            AttributeBody::Data(Data::with_value(buf[0..amt].to_vec())),
+        ];
+
+        let _ = connection.send(&handlers::build_indication(MessageType::Data, &id, body));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A client of its own for each port, all on the one server socket
+    fn connection(socket:&Arc<UdpSocket>, port:u16) -> Connection {
+        Connection::udp(Arc::clone(socket), SocketAddr::from(([127, 0, 0, 1], port)), socket.local_addr().unwrap())
+    }
+
+    #[test]
+    fn allocations_past_a_quota_are_refused() {
+        let allocations = Allocations::new(IpAddr::from([127, 0, 0, 1]), vec![], Some(2), Some(3));
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+        let allocate = |port:u16, username:&str| {
+            allocations.allocate(&connection(&socket, port), &[port as u8; 12], requested_transport::UDP, None, username).map(|_| ())
+        };
+
+        assert!(allocate(1, "alice") == Ok(()));
+        assert!(allocate(2, "alice") == Ok(()));
+        assert!(allocate(3, "alice") == Err(ErrorCodeType::AllocationQuotaReached));
+        // A retransmitted Allocate is answered as before rather than counted again
+        assert!(allocate(2, "alice") == Ok(()));
+
+        assert!(allocate(3, "bob") == Ok(()));
+        assert!(allocate(4, "carol") == Err(ErrorCodeType::AllocationQuotaReached));
+
+        // Deleting an allocation makes room for another
+        assert!(allocations.refresh(&connection(&socket, 1).tuple, Some(0), "alice") == Ok(0));
+        assert!(allocate(4, "carol") == Ok(()));
+    }
+
+    #[test]
+    fn data_connections_are_bound_by_the_allocation_owner_only() {
+        let allocations = Allocations::new(IpAddr::from([127, 0, 0, 1]), vec![], None, None);
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+        let control = connection(&socket, 1);
+        let data = connection(&socket, 2).tuple;
+        let peer = TcpListener::bind("127.0.0.1:0").unwrap();
+        let peer_address = peer.local_addr().unwrap();
+
+        assert!(allocations.allocate(&control, &[1; 12], requested_transport::TCP, None, "alice").is_ok());
+        assert!(allocations.permit(&control.tuple, &[peer_address.ip()], "alice") == Ok(()));
+        let id = match allocations.connect(&control.tuple, &peer_address, "alice") {
+            Ok(id) => id,
+            Err(_) => panic!("no peer connection"),
+        };
+
+        assert!(allocations.bind_connection(&data, id, "mallory") == Err(ErrorCodeType::WrongCredentials));
+        assert!(allocations.bind_connection(&data, id, "alice") == Ok(()));
+    }
+}
@@ -0,0 +1,59 @@
+use crate::attributes::Attribute;
+use crate::error::DecodeError;
+use crate::header::MessageHeader;
+
+const CHANGE_IP: u32 = 0x04;
+const CHANGE_PORT: u32 = 0x02;
+
+pub struct ChangeRequest {
+    flags: Option<u32>,
+}
+impl ChangeRequest {
+    pub fn with_flags(change_ip:bool, change_port:bool) -> ChangeRequest {
+        let mut flags = 0;
+        if change_ip {
+            flags |= CHANGE_IP;
+        }
+        if change_port {
+            flags |= CHANGE_PORT;
+        }
+        ChangeRequest {
+            flags: Some(flags),
+        }
+    }
+
+    pub fn change_ip(&self) -> bool {
+        match self.flags {
+            Some(flags) => flags & CHANGE_IP != 0,
+            None => false,
+        }
+    }
+
+    pub fn change_port(&self) -> bool {
+        match self.flags {
+            Some(flags) => flags & CHANGE_PORT != 0,
+            None => false,
+        }
+    }
+}
+impl Attribute for ChangeRequest {
+    fn new() -> ChangeRequest {
+        ChangeRequest {
+            flags: None,
+        }
+    }
+
+    fn serialise(&self) -> Option<Vec<u8>> {
+        self.flags.map(|flags| flags.to_be_bytes().to_vec())
+    }
+
+    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), DecodeError> {
+        if body.len() != 4 {
+            return Err(DecodeError::BadLength);
+        }
+
+        self.flags = Some(u32::from_be_bytes([body[0], body[1], body[2], body[3]]));
+
+        Ok(())
+    }
+}
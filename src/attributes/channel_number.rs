@@ -0,0 +1,42 @@
+use crate::attributes::Attribute;
+use crate::error::DecodeError;
+use crate::header::MessageHeader;
+
+pub struct ChannelNumber {
+    channel: Option<u16>,
+}
+impl ChannelNumber {
+    pub fn with_value(channel:u16) -> ChannelNumber {
+        ChannelNumber {
+            channel: Some(channel),
+        }
+    }
+
+    pub fn value(&self) -> Option<u16> {
+        self.channel
+    }
+}
+impl Attribute for ChannelNumber {
+    fn new() -> ChannelNumber {
+        ChannelNumber {
+            channel: None,
+        }
+    }
+
+    fn serialise(&self) -> Option<Vec<u8>> {
+        self.channel.map(|channel| {
+            let bytes = channel.to_be_bytes();
+            vec![bytes[0], bytes[1], 0, 0]
+        })
+    }
+
+    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), DecodeError> {
+        if body.len() != 4 {
+            return Err(DecodeError::BadLength);
+        }
+
+        self.channel = Some(u16::from_be_bytes([body[0], body[1]]));
+
+        Ok(())
+    }
+}
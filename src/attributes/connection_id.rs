@@ -0,0 +1,39 @@
+use crate::attributes::Attribute;
+use crate::error::DecodeError;
+use crate::header::MessageHeader;
+
+pub struct ConnectionId {
+    connection_id: Option<u32>,
+}
+impl ConnectionId {
+    pub fn with_value(connection_id:u32) -> ConnectionId {
+        ConnectionId {
+            connection_id: Some(connection_id),
+        }
+    }
+
+    pub fn value(&self) -> Option<u32> {
+        self.connection_id
+    }
+}
+impl Attribute for ConnectionId {
+    fn new() -> ConnectionId {
+        ConnectionId {
+            connection_id: None,
+        }
+    }
+
+    fn serialise(&self) -> Option<Vec<u8>> {
+        self.connection_id.map(|connection_id| connection_id.to_be_bytes().to_vec())
+    }
+
+    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), DecodeError> {
+        if body.len() != 4 {
+            return Err(DecodeError::BadLength);
+        }
+
+        self.connection_id = Some(u32::from_be_bytes([body[0], body[1], body[2], body[3]]));
+
+        Ok(())
+    }
+}
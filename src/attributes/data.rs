@@ -0,0 +1,34 @@
+use crate::attributes::Attribute;
+use crate::error::DecodeError;
+use crate::header::MessageHeader;
+
+pub struct Data {
+    data: Option<Vec<u8>>,
+}
+impl Data {
+    pub fn with_value(data:Vec<u8>) -> Data {
+        Data {
+            data: Some(data),
+        }
+    }
+
+    pub fn value(&self) -> Option<&[u8]> {
+        self.data.as_deref()
+    }
+}
+impl Attribute for Data {
+    fn new() -> Data {
+        Data {
+            data: None,
+        }
+    }
+
+    fn serialise(&self) -> Option<Vec<u8>> {
+        self.data.clone()
+    }
+
+    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), DecodeError> {
+        self.data = Some(body.to_vec());
+        Ok(())
+    }
+}
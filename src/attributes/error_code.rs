@@ -1,13 +1,25 @@
 use crate::attributes::Attribute;
+use crate::error::DecodeError;
 use crate::header::MessageHeader;
 
+#[derive(Copy, Clone, PartialEq)]
 pub enum ErrorCodeType {
-    TryAlternate,       // 300    
-    BadRequest,         // 400
-    Unauthorised,       // 401
-    UnknownAttribute,   // 420
-    StaleNonce,         // 438
-    ServerError,        // 500
+    TryAlternate,               // 300
+    BadRequest,                 // 400
+    Unauthorised,               // 401
+    Forbidden,                  // 403
+    UnknownAttribute,           // 420
+    AllocationMismatch,         // 437
+    StaleNonce,                 // 438
+    WrongCredentials,           // 441
+    UnsupportedTransport,       // 442
+    ConnectionAlreadyExists,    // 446
+    ConnectionTimeoutOrFailure, // 447
+    AllocationQuotaReached,     // 486
+    ServerError,                // 500
+    InsufficientCapacity,       // 508
+    // Any other code, such as one from an extension, as class * 100 + number
+    Other(u16),
 }
 pub struct ErrorCode {
     code: Option<ErrorCodeType>,
@@ -19,16 +31,70 @@ impl ErrorCode {
             300 => Some(ErrorCodeType::TryAlternate),
             400 => Some(ErrorCodeType::BadRequest),
             401 => Some(ErrorCodeType::Unauthorised),
+            403 => Some(ErrorCodeType::Forbidden),
             420 => Some(ErrorCodeType::UnknownAttribute),
+            437 => Some(ErrorCodeType::AllocationMismatch),
             438 => Some(ErrorCodeType::StaleNonce),
+            441 => Some(ErrorCodeType::WrongCredentials),
+            442 => Some(ErrorCodeType::UnsupportedTransport),
+            446 => Some(ErrorCodeType::ConnectionAlreadyExists),
+            447 => Some(ErrorCodeType::ConnectionTimeoutOrFailure),
+            486 => Some(ErrorCodeType::AllocationQuotaReached),
             500 => Some(ErrorCodeType::ServerError),
-            _ => None,
+            508 => Some(ErrorCodeType::InsufficientCapacity),
+            code => Some(ErrorCodeType::Other(code)),
         };
         ErrorCode {
             code,
             message: Some(message),
         }
     }
+
+    pub fn with_type(code:ErrorCodeType) -> ErrorCode {
+        let (code, message) = match code {
+            ErrorCodeType::TryAlternate => (300, "Try Alternate"),
+            ErrorCodeType::BadRequest => (400, "Bad Request"),
+            ErrorCodeType::Unauthorised => (401, "Unauthorized"),
+            ErrorCodeType::Forbidden => (403, "Forbidden"),
+            ErrorCodeType::UnknownAttribute => (420, "Unknown Attribute"),
+            ErrorCodeType::AllocationMismatch => (437, "Allocation Mismatch"),
+            ErrorCodeType::StaleNonce => (438, "Stale Nonce"),
+            ErrorCodeType::WrongCredentials => (441, "Wrong Credentials"),
+            ErrorCodeType::UnsupportedTransport => (442, "Unsupported Transport Protocol"),
+            ErrorCodeType::ConnectionAlreadyExists => (446, "Connection Already Exists"),
+            ErrorCodeType::ConnectionTimeoutOrFailure => (447, "Connection Timeout or Failure"),
+            ErrorCodeType::AllocationQuotaReached => (486, "Allocation Quota Reached"),
+            ErrorCodeType::ServerError => (500, "Server Error"),
+            ErrorCodeType::InsufficientCapacity => (508, "Insufficient Capacity"),
+            ErrorCodeType::Other(code) => (code, ""),
+        };
+        ErrorCode::with_code(code, String::from(message))
+    }
+
+    pub fn code_type(&self) -> Option<ErrorCodeType> {
+        self.code
+    }
+
+    // The numeric code, whether or not it has a type of its own
+    pub fn code(&self) -> Option<u16> {
+        match self.code? {
+            ErrorCodeType::TryAlternate => Some(300),
+            ErrorCodeType::BadRequest => Some(400),
+            ErrorCodeType::Unauthorised => Some(401),
+            ErrorCodeType::Forbidden => Some(403),
+            ErrorCodeType::UnknownAttribute => Some(420),
+            ErrorCodeType::AllocationMismatch => Some(437),
+            ErrorCodeType::StaleNonce => Some(438),
+            ErrorCodeType::WrongCredentials => Some(441),
+            ErrorCodeType::UnsupportedTransport => Some(442),
+            ErrorCodeType::ConnectionAlreadyExists => Some(446),
+            ErrorCodeType::ConnectionTimeoutOrFailure => Some(447),
+            ErrorCodeType::AllocationQuotaReached => Some(486),
+            ErrorCodeType::ServerError => Some(500),
+            ErrorCodeType::InsufficientCapacity => Some(508),
+            ErrorCodeType::Other(code) => Some(code),
+        }
+    }
 }
 impl Attribute for ErrorCode {
     fn new() -> ErrorCode {
@@ -39,14 +105,10 @@ impl Attribute for ErrorCode {
     }
 
     fn serialise(&self) -> Option<Vec<u8>> {
-        let code = match self.code {
-            Some(ErrorCodeType::TryAlternate) => 300,
-            Some(ErrorCodeType::BadRequest) => 400,
-            Some(ErrorCodeType::Unauthorised) => 401,
-            Some(ErrorCodeType::UnknownAttribute) => 420,
-            Some(ErrorCodeType::StaleNonce) => 438,
-            Some(ErrorCodeType::ServerError) => 500,
-            None => return None,
+        // Classes run from 3 to 6 (RFC 5389 section 15.6)
+        let code = match self.code() {
+            Some(code) if (300..700).contains(&code) => code,
+            _ => return None,
         };
         let class = (code / 100) as u8;
         let error_number = (code % 100) as u8;
@@ -61,31 +123,29 @@ impl Attribute for ErrorCode {
         Some(buf)
     }
 
-    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), ()> {
+    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), DecodeError> {
         if body.len() < 4 {
-            return Err(());
+            return Err(DecodeError::Truncated);
+        }
+        // The reason phrase is at most 128 characters, which is up to 763 bytes
+        if body.len() > 4 + 763 {
+            return Err(DecodeError::TooLong);
         }
 
-        let class = body[3] & 0b00000111;
-        let code = class as u16 * 100 + body[4] as u16 % 100;
-        let error_code = match code {
-            300 => ErrorCodeType::TryAlternate,
-            400 => ErrorCodeType::BadRequest,
-            401 => ErrorCodeType::Unauthorised,
-            420 => ErrorCodeType::UnknownAttribute,
-            438 => ErrorCodeType::StaleNonce,
-            500 => ErrorCodeType::ServerError,
-            _ => return Err(()),
-        };
+        // The hundreds digit is the class, the rest is the number. Codes the crate has no type
+        // for are kept as they are, so long as they are well-formed.
+        let class = body[2] & 0b00000111;
+        let code = class as u16 * 100 + body[3] as u16;
+        if !(3..=6).contains(&class) || body[3] > 99 {
+            return Err(DecodeError::UnknownErrorCode(code));
+        }
 
-        let message_bytes = &body[5..body.len()];
-        let message = match String::from_utf8(message_bytes.to_vec()) {
+        let message = match String::from_utf8(body[4..].to_vec()) {
             Ok(message) => message,
-            Err(_) => return Err(()),
+            Err(_) => return Err(DecodeError::InvalidUtf8),
         };
 
-        self.code = Some(error_code);
-        self.message = Some(message);
+        *self = ErrorCode::with_code(code, message);
 
         Ok(())
     }
@@ -0,0 +1,75 @@
+extern crate crc32fast;
+
+use crate::attributes::Attribute;
+use crate::error::DecodeError;
+use crate::header::MessageHeader;
+
+const FINGERPRINT_XOR: u32 = 0x5354554e;
+
+pub struct Fingerprint {
+    crc: Option<u32>,
+    offset: Option<usize>,
+}
+impl Fingerprint {
+    // message is the header and every attribute that precedes FINGERPRINT
+    pub fn with_message(message:&[u8]) -> Fingerprint {
+        Fingerprint {
+            crc: calculate_crc(message),
+            offset: None,
+        }
+    }
+
+    // Offset of the attribute from the start of the message body
+    pub fn set_offset(&mut self, offset:usize) {
+        self.offset = Some(offset);
+    }
+
+    // message is the full message, header included
+    pub fn verify(&self, message:&[u8]) -> bool {
+        let (crc, offset) = match (self.crc, self.offset) {
+            (Some(crc), Some(offset)) => (crc, offset),
+            _ => return false,
+        };
+        if message.len() < 20 + offset {
+            return false;
+        }
+
+        calculate_crc(&message[0..(20 + offset)]) == Some(crc)
+    }
+}
+impl Attribute for Fingerprint {
+    fn new() -> Fingerprint {
+        Fingerprint {
+            crc: None,
+            offset: None,
+        }
+    }
+
+    fn serialise(&self) -> Option<Vec<u8>> {
+        self.crc.map(|crc| crc.to_be_bytes().to_vec())
+    }
+
+    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), DecodeError> {
+        if body.len() != 4 {
+            return Err(DecodeError::BadLength);
+        }
+
+        self.crc = Some(u32::from_be_bytes([body[0], body[1], body[2], body[3]]));
+
+        Ok(())
+    }
+}
+
+fn calculate_crc(message:&[u8]) -> Option<u32> {
+    if message.len() < 20 {
+        return None;
+    }
+
+    // The length field must cover up to the end of FINGERPRINT itself
+    let mut message = message.to_vec();
+    let length = ((message.len() - 20 + 8) as u16).to_be_bytes();
+    message[2] = length[0];
+    message[3] = length[1];
+
+    Some(crc32fast::hash(&message) ^ FINGERPRINT_XOR)
+}
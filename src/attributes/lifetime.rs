@@ -0,0 +1,39 @@
+use crate::attributes::Attribute;
+use crate::error::DecodeError;
+use crate::header::MessageHeader;
+
+pub struct Lifetime {
+    lifetime: Option<u32>,
+}
+impl Lifetime {
+    pub fn with_value(lifetime:u32) -> Lifetime {
+        Lifetime {
+            lifetime: Some(lifetime),
+        }
+    }
+
+    pub fn value(&self) -> Option<u32> {
+        self.lifetime
+    }
+}
+impl Attribute for Lifetime {
+    fn new() -> Lifetime {
+        Lifetime {
+            lifetime: None,
+        }
+    }
+
+    fn serialise(&self) -> Option<Vec<u8>> {
+        self.lifetime.map(|lifetime| lifetime.to_be_bytes().to_vec())
+    }
+
+    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), DecodeError> {
+        if body.len() != 4 {
+            return Err(DecodeError::BadLength);
+        }
+
+        self.lifetime = Some(u32::from_be_bytes([body[0], body[1], body[2], body[3]]));
+
+        Ok(())
+    }
+}
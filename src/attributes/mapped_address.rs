@@ -1,4 +1,5 @@
 use crate::attributes::Attribute;
+use crate::error::DecodeError;
 use crate::header::MessageHeader;
 use std::net::{ IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr };
 
@@ -11,6 +12,10 @@ impl MappedAddress {
             address: Some(address),
         }
     }
+
+    pub fn address(&self) -> Option<SocketAddr> {
+        self.address
+    }
 }
 impl Attribute for MappedAddress {
     fn new() -> MappedAddress {
@@ -20,10 +25,7 @@ impl Attribute for MappedAddress {
     }
 
     fn serialise(&self) -> Option<Vec<u8>>{
-        let address = match self.address {
-            Some(address) => address,
-            None => return None,
-        };
+        let address = self.address?;
 
         let family = if address.is_ipv4() {
             1
@@ -41,39 +43,27 @@ impl Attribute for MappedAddress {
             IpAddr::V6(address) => address.octets().to_vec(),
         };
 
-        let buf = vec![0, family, p1, p2].iter()
-                                         .chain(&address)
-                                         .map(|b| *b)
-                                         .collect::<Vec<u8>>();
+        let buf = [0, family, p1, p2].iter()
+                                     .chain(&address)
+                                     .copied()
+                                     .collect::<Vec<u8>>();
         Some(buf)
     }
 
-    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), ()> {
+    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), DecodeError> {
         if body.len() < 4 {
-            return Err(());
+            return Err(DecodeError::Truncated);
         }
         let port = u16::from_be_bytes([body[2], body[3]]);
         let address = match (body[1], body.len()) {
-            (1, 8) => {
-                if body.len() < 8 {
-                    return Err(());
-                }
-                SocketAddr::new(IpAddr::V4(Ipv4Addr::new(body[4], body[5], body[6], body[7])), port)
-            },
+            (1, 8) => SocketAddr::new(IpAddr::V4(Ipv4Addr::new(body[4], body[5], body[6], body[7])), port),
             (2, 20) => {
-                if body.len() < 20 {
-                    return Err(());
-                }
-                let address_bits = body.chunks(2)
-                                       .skip(2)
-                                       .map(|chunk| u16::from_be_bytes([chunk[0], chunk[1]]))
-                                       .collect::<Vec<u16>>();
-                if address_bits.len() < 8 {
-                    return Err(());
-                }
-                SocketAddr::new(IpAddr::V6(Ipv6Addr::new(address_bits[0], address_bits[1], address_bits[2], address_bits[3], address_bits[4], address_bits[5], address_bits[6], address_bits[7])), port)
+                let mut octets = [0; 16];
+                octets.copy_from_slice(&body[4..20]);
+                SocketAddr::new(IpAddr::V6(Ipv6Addr::from(octets)), port)
             },
-            _ => return Err(()),
+            (1, _) | (2, _) => return Err(DecodeError::BadLength),
+            (family, _) => return Err(DecodeError::BadFamily(family)),
         };
         self.address = Some(address);
         Ok(())
@@ -1,41 +1,103 @@
+extern crate hmac;
+extern crate sha1;
+
 use crate::attributes::Attribute;
+use crate::error::DecodeError;
 use crate::header::MessageHeader;
 
+use hmac::{ Hmac, Mac };
+use sha1::Sha1;
+
 pub struct MessageIntegrity {
     hash: Option<[u8; 20]>,
+    offset: Option<usize>,
 }
 impl MessageIntegrity {
     pub fn with_hash(hash:[u8; 20]) -> MessageIntegrity {
         MessageIntegrity {
             hash: Some(hash),
+            offset: None,
+        }
+    }
+
+    // message is the header and every attribute that precedes MESSAGE-INTEGRITY
+    pub fn with_message(message:&[u8], key:&[u8]) -> MessageIntegrity {
+        MessageIntegrity {
+            hash: calculate_hash(message, key),
+            offset: None,
+        }
+    }
+
+    // Offset of the attribute from the start of the message body
+    pub fn set_offset(&mut self, offset:usize) {
+        self.offset = Some(offset);
+    }
+
+    // message is the full message, header included
+    pub fn verify(&self, message:&[u8], key:&[u8]) -> bool {
+        let (hash, offset) = match (self.hash, self.offset) {
+            (Some(hash), Some(offset)) => (hash, offset),
+            _ => return false,
+        };
+        if message.len() < 20 + offset {
+            return false;
         }
+
+        let mac = match prepare_mac(&message[0..(20 + offset)], key) {
+            Some(mac) => mac,
+            None => return false,
+        };
+        mac.verify_slice(&hash).is_ok()
     }
 }
 impl Attribute for MessageIntegrity {
     fn new() -> MessageIntegrity {
         MessageIntegrity {
             hash: None,
+            offset: None,
         }
     }
 
     fn serialise(&self) -> Option<Vec<u8>> {
-        match self.hash {
-            Some(hash) => Some(hash.to_vec()),
-            None => None,
-        }
+        self.hash.map(|hash| hash.to_vec())
     }
 
-    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), ()> {
-        if body.len() < 20 {
-            return Err(());
+    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), DecodeError> {
+        if body.len() != 20 {
+            return Err(DecodeError::BadLength);
         }
 
         let mut buf = [0; 20];
-        for i in 0..20 {
-            buf[i] = body[i];
-        }
+        buf.copy_from_slice(&body[0..20]);
         self.hash = Some(buf);
 
         Ok(())
     }
 }
+
+fn prepare_mac(message:&[u8], key:&[u8]) -> Option<Hmac<Sha1>> {
+    if message.len() < 20 {
+        return None;
+    }
+
+    // The length field must cover up to the end of MESSAGE-INTEGRITY itself
+    let mut message = message.to_vec();
+    let length = ((message.len() - 20 + 24) as u16).to_be_bytes();
+    message[2] = length[0];
+    message[3] = length[1];
+
+    let mut mac = match Hmac::<Sha1>::new_from_slice(key) {
+        Ok(mac) => mac,
+        Err(_) => return None,
+    };
+    mac.update(&message);
+    Some(mac)
+}
+
+fn calculate_hash(message:&[u8], key:&[u8]) -> Option<[u8; 20]> {
+    let mac = prepare_mac(message, key)?;
+
+    let mut hash = [0; 20];
+    hash.copy_from_slice(&mac.finalize().into_bytes());
+    Some(hash)
+}
@@ -1,6 +1,12 @@
+use crate::error::DecodeError;
 use crate::header::MessageHeader;
+use crate::message::{ self, RawAttribute };
 
-use std::collections::HashMap;
+use std::any::Any;
+use std::slice;
+
+pub mod registry;
+use registry::Registry;
 
 pub mod mapped_address;
 use mapped_address::MappedAddress;
@@ -18,8 +24,38 @@ pub mod nonce;
 use nonce::Nonce;
 pub mod message_integrity;
 use message_integrity::MessageIntegrity;
+pub mod fingerprint;
+use fingerprint::Fingerprint;
+pub mod lifetime;
+use lifetime::Lifetime;
+pub mod xor_relayed_address;
+use xor_relayed_address::XorRelayedAddress;
+pub mod requested_transport;
+use requested_transport::RequestedTransport;
+pub mod xor_peer_address;
+use xor_peer_address::XorPeerAddress;
+pub mod data;
+use data::Data;
+pub mod channel_number;
+use channel_number::ChannelNumber;
+pub mod connection_id;
+use connection_id::ConnectionId;
+pub mod change_request;
+use change_request::ChangeRequest;
+pub mod padding;
+use padding::Padding;
+pub mod response_port;
+use response_port::ResponsePort;
+pub mod response_origin;
+use response_origin::ResponseOrigin;
+pub mod other_address;
+use other_address::OtherAddress;
+pub mod priority;
+use priority::Priority;
+pub mod use_candidate;
+use use_candidate::UseCandidate;
 
-#[derive(Hash, PartialEq, Eq)]
+#[derive(Copy, Clone, Hash, PartialEq, Eq)]
 pub enum MessageAttribute {
     MappedAddress,
     Username,
@@ -29,13 +65,48 @@ pub enum MessageAttribute {
     Realm,
     Nonce,
     XorMappedAddress,
+    Fingerprint,
+    Lifetime,
+    XorRelayedAddress,
+    RequestedTransport,
+    XorPeerAddress,
+    Data,
+    ChannelNumber,
+    ConnectionId,
+    ChangeRequest,
+    Padding,
+    ResponsePort,
+    ResponseOrigin,
+    OtherAddress,
+    Priority,
+    UseCandidate,
+    // An attribute type registered with the registry, by type code
+    Custom(u16),
+    // Any attribute without a type of its own, by type code
+    Unknown(u16),
 }
 
-trait Attribute {
+pub trait Attribute {
     fn new() -> Self;
 
     fn serialise(&self) -> Option<Vec<u8>>;
-    fn deserialise(&mut self, body:&[u8], header:&MessageHeader) -> Result<(), ()>;
+    fn deserialise(&mut self, body:&[u8], header:&MessageHeader) -> Result<(), DecodeError>;
+}
+
+// What a registered attribute type is held as once decoded. Implemented for every
+// Attribute that can be shared between threads.
+pub trait CustomAttribute: Send + Sync {
+    fn serialise_value(&self) -> Option<Vec<u8>>;
+    fn as_any(&self) -> &dyn Any;
+}
+impl<T: Attribute + Send + Sync + 'static> CustomAttribute for T {
+    fn serialise_value(&self) -> Option<Vec<u8>> {
+        self.serialise()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
 pub enum AttributeBody {
@@ -47,59 +118,128 @@ pub enum AttributeBody {
     Nonce(Nonce),
     ErrorCode(ErrorCode),
     UnknownAttributes(UnknownAttributes),
+    Fingerprint(Fingerprint),
+    Lifetime(Lifetime),
+    XorRelayedAddress(XorRelayedAddress),
+    RequestedTransport(RequestedTransport),
+    XorPeerAddress(XorPeerAddress),
+    Data(Data),
+    ChannelNumber(ChannelNumber),
+    ConnectionId(ConnectionId),
+    ChangeRequest(ChangeRequest),
+    Padding(Padding),
+    ResponsePort(ResponsePort),
+    ResponseOrigin(ResponseOrigin),
+    OtherAddress(OtherAddress),
+    Priority(Priority),
+    UseCandidate(UseCandidate),
+    Custom {
+        type_code: u16,
+        value: Box<dyn CustomAttribute>,
+    },
+    // Kept as it was received, so it can be passed on unchanged
+    Unknown {
+        type_code: u16,
+        value: Vec<u8>,
+    },
+}
+impl AttributeBody {
+    // The value of a registered attribute, if it was registered as T
+    pub fn custom<T: 'static>(&self) -> Option<&T> {
+        match self {
+            AttributeBody::Custom { value, .. } => value.as_any().downcast_ref::<T>(),
+            _ => None,
+        }
+    }
+}
+
+pub struct DecodedAttribute {
+    pub attribute: MessageAttribute,
+    pub body: AttributeBody,
+    // Offset of the attribute from the start of the message body
+    pub offset: usize,
+}
+
+// Decoded attributes in the order they appear in the message, repeats included
+pub struct Attributes {
+    attributes: Vec<DecodedAttribute>,
 }
+impl Attributes {
+    pub fn iter(&self) -> slice::Iter<'_, DecodedAttribute> {
+        self.attributes.iter()
+    }
+
+    // The first attribute of the given type
+    pub fn get(&self, attribute:MessageAttribute) -> Option<&AttributeBody> {
+        self.get_all(attribute).next()
+    }
+
+    // Every attribute of the given type, in order
+    pub fn get_all(&self, attribute:MessageAttribute) -> impl Iterator<Item = &AttributeBody> {
+        self.attributes.iter()
+                       .filter(move |decoded| decoded.attribute == attribute)
+                       .map(|decoded| &decoded.body)
+    }
 
-enum AttributeError {
-    TooShort,
-    ParsingError(usize),
-}
-
-pub fn get_attributes(body: &[u8], header:&MessageHeader) -> HashMap<MessageAttribute, AttributeBody> {
-    let mut attributes = HashMap::new();
-    let mut i = 0;
-    while i < header.length as usize {
-        match get_attribute(body, header, i) {
-            Ok((attribute, body, length)) => {
-                let _ = attributes.insert(attribute, body);   
-                i += length;
-            },
-            Err(AttributeError::TooShort) => break,
-            Err(AttributeError::ParsingError(length)) => {
-                i += length;
-                continue;
-            },
-        };
+    pub fn contains(&self, attribute:MessageAttribute) -> bool {
+        self.get(attribute).is_some()
     }
 
-    attributes
+    pub(crate) fn decode(raw_attributes:&[RawAttribute], header:&MessageHeader, registry:Option<&Registry>) -> Result<Attributes, DecodeError> {
+        let mut attributes = vec![];
+        for raw in raw_attributes {
+            let (attribute, body) = decode_attribute(raw.type_code, raw.value, header, raw.offset, registry)?;
+            attributes.push(DecodedAttribute {
+                attribute,
+                body,
+                offset: raw.offset,
+            });
+        }
+        Ok(Attributes {
+            attributes,
+        })
+    }
+}
+
+// Fails if an attribute is truncated or a known attribute is malformed. Unknown
+// attributes are kept, and comprehension-required ones (0x0000-0x7FFF) are also
+// collected separately.
+pub fn get_attributes(body: &[u8], header:&MessageHeader) -> Result<(Attributes, UnknownAttributes), DecodeError> {
+    get_attributes_registry(body, header, None)
 }
 
-fn get_attribute(body: &[u8], header:&MessageHeader, index: usize) -> Result<(MessageAttribute, AttributeBody, usize), AttributeError> {
-    let mut i = index;
+// As get_attributes, decoding the attribute types in the registry too
+pub fn get_attributes_with(body: &[u8], header:&MessageHeader, registry:&Registry) -> Result<(Attributes, UnknownAttributes), DecodeError> {
+    get_attributes_registry(body, header, Some(registry))
+}
 
-    if body.len() < index + 4 {
-        return Err(AttributeError::TooShort);
+// Attributes are split out of the body exactly as Message::decode does it
+fn get_attributes_registry(body: &[u8], header:&MessageHeader, registry:Option<&Registry>) -> Result<(Attributes, UnknownAttributes), DecodeError> {
+    if body.len() < header.length as usize {
+        return Err(DecodeError::Truncated);
     }
-    
-    let attribute_type = (body[i] as u16) * 256 + (body[i + 1] as u16);
-    i += 2;
-    let attribute_length = ((body[i] as u16) * 256 + (body[i + 1] as u16)) as usize;
-    i += 2;
-
-    if body.len() < i + attribute_length {
-        return Err(AttributeError::TooShort);
+    let (raw_attributes, unknown) = message::read_attributes(&body[0..(header.length as usize)], registry)?;
+    let attributes = Attributes::decode(&raw_attributes, header, registry)?;
+
+    let mut unknown_attributes = UnknownAttributes::new_empty();
+    for type_code in unknown {
+        unknown_attributes.add(type_code);
     }
-    let body = &body[i..(i + attribute_length)];
-    let attribute_type = match attribute_type {
-        1 => MessageAttribute::MappedAddress,
-        32 => MessageAttribute::XorMappedAddress,
-        8 => MessageAttribute::MessageIntegrity,
-        6 => MessageAttribute::Username,
-        20 => MessageAttribute::Realm,
-        21 => MessageAttribute::Nonce,
-        9 => MessageAttribute::ErrorCode,
-        10 => MessageAttribute::UnknownAttributes,
-        _ => return Err(AttributeError::ParsingError(attribute_length)),
+    Ok((attributes, unknown_attributes))
+}
+
+// Decodes an attribute value; offset is where the attribute starts in the message body
+pub(crate) fn decode_attribute(attribute_type:u16, body:&[u8], header:&MessageHeader, offset:usize, registry:Option<&Registry>) -> Result<(MessageAttribute, AttributeBody), DecodeError> {
+    let attribute_type = match builtin(attribute_type) {
+        Some(attribute_type) => attribute_type,
+        None => match registry.and_then(|registry| registry.decode(attribute_type, body, header)) {
+            Some(Ok(value)) => return Ok((MessageAttribute::Custom(attribute_type), AttributeBody::Custom {
+                type_code: attribute_type,
+                value,
+            })),
+            Some(Err(error)) => return Err(error),
+            None => MessageAttribute::Unknown(attribute_type),
+        },
     };
 
     let attribute = match attribute_type {
@@ -107,62 +247,218 @@ fn get_attribute(body: &[u8], header:&MessageHeader, index: usize) -> Result<(Me
             let mut attribute = MappedAddress::new();
             match attribute.deserialise(body, header) {
                 Ok(_) => AttributeBody::MappedAddress(attribute),
-                Err(_) => return Err(AttributeError::ParsingError(attribute_length)),
+                Err(error) => return Err(error),
             }
         },
         MessageAttribute::XorMappedAddress => {
             let mut attribute = XorMappedAddress::new();
             match attribute.deserialise(body, header) {
                 Ok(_) => AttributeBody::XorMappedAddress(attribute),
-                Err(_) => return Err(AttributeError::ParsingError(attribute_length)),
+                Err(error) => return Err(error),
             }
         },
         MessageAttribute::MessageIntegrity => {
             let mut attribute = MessageIntegrity::new();
             match attribute.deserialise(body, header) {
-                Ok(_) => AttributeBody::MessageIntegrity(attribute),
-                Err(_) => return Err(AttributeError::ParsingError(attribute_length)),
+                Ok(_) => {
+                    attribute.set_offset(offset);
+                    AttributeBody::MessageIntegrity(attribute)
+                },
+                Err(error) => return Err(error),
             }
         },
         MessageAttribute::Username => {
             let mut attribute = Username::new();
             match attribute.deserialise(body, header) {
                 Ok(_) => AttributeBody::Username(attribute),
-                Err(_) => return Err(AttributeError::ParsingError(attribute_length)),
+                Err(error) => return Err(error),
             }
         },
         MessageAttribute::Realm => {
             let mut attribute = Realm::new();
             match attribute.deserialise(body, header) {
                 Ok(_) => AttributeBody::Realm(attribute),
-                Err(_) => return Err(AttributeError::ParsingError(attribute_length)),
+                Err(error) => return Err(error),
             }
         },
         MessageAttribute::Nonce => {
             let mut attribute = Nonce::new();
             match attribute.deserialise(body, header) {
                 Ok(_) => AttributeBody::Nonce(attribute),
-                Err(_) => return Err(AttributeError::ParsingError(attribute_length)),
+                Err(error) => return Err(error),
             }
         },
         MessageAttribute::ErrorCode => {
             let mut attribute = ErrorCode::new();
             match attribute.deserialise(body, header) {
                 Ok(_) => AttributeBody::ErrorCode(attribute),
-                Err(_) => return Err(AttributeError::ParsingError(attribute_length)),
+                Err(error) => return Err(error),
             }
         },
         MessageAttribute::UnknownAttributes => {
             let mut attribute = UnknownAttributes::new();
             match attribute.deserialise(body, header) {
                 Ok(_) => AttributeBody::UnknownAttributes(attribute),
-                Err(_) => return Err(AttributeError::ParsingError(attribute_length)),
+                Err(error) => return Err(error),
+            }
+        },
+        MessageAttribute::Fingerprint => {
+            let mut attribute = Fingerprint::new();
+            match attribute.deserialise(body, header) {
+                Ok(_) => {
+                    attribute.set_offset(offset);
+                    AttributeBody::Fingerprint(attribute)
+                },
+                Err(error) => return Err(error),
+            }
+        },
+        MessageAttribute::Lifetime => {
+            let mut attribute = Lifetime::new();
+            match attribute.deserialise(body, header) {
+                Ok(_) => AttributeBody::Lifetime(attribute),
+                Err(error) => return Err(error),
+            }
+        },
+        MessageAttribute::XorRelayedAddress => {
+            let mut attribute = XorRelayedAddress::new();
+            match attribute.deserialise(body, header) {
+                Ok(_) => AttributeBody::XorRelayedAddress(attribute),
+                Err(error) => return Err(error),
+            }
+        },
+        MessageAttribute::RequestedTransport => {
+            let mut attribute = RequestedTransport::new();
+            match attribute.deserialise(body, header) {
+                Ok(_) => AttributeBody::RequestedTransport(attribute),
+                Err(error) => return Err(error),
+            }
+        },
+        MessageAttribute::XorPeerAddress => {
+            let mut attribute = XorPeerAddress::new();
+            match attribute.deserialise(body, header) {
+                Ok(_) => AttributeBody::XorPeerAddress(attribute),
+                Err(error) => return Err(error),
+            }
+        },
+        MessageAttribute::Data => {
+            let mut attribute = Data::new();
+            match attribute.deserialise(body, header) {
+                Ok(_) => AttributeBody::Data(attribute),
+                Err(error) => return Err(error),
+            }
+        },
+        MessageAttribute::ChannelNumber => {
+            let mut attribute = ChannelNumber::new();
+            match attribute.deserialise(body, header) {
+                Ok(_) => AttributeBody::ChannelNumber(attribute),
+                Err(error) => return Err(error),
+            }
+        },
+        MessageAttribute::ConnectionId => {
+            let mut attribute = ConnectionId::new();
+            match attribute.deserialise(body, header) {
+                Ok(_) => AttributeBody::ConnectionId(attribute),
+                Err(error) => return Err(error),
+            }
+        },
+        MessageAttribute::ChangeRequest => {
+            let mut attribute = ChangeRequest::new();
+            match attribute.deserialise(body, header) {
+                Ok(_) => AttributeBody::ChangeRequest(attribute),
+                Err(error) => return Err(error),
+            }
+        },
+        MessageAttribute::Padding => {
+            let mut attribute = Padding::new();
+            match attribute.deserialise(body, header) {
+                Ok(_) => AttributeBody::Padding(attribute),
+                Err(error) => return Err(error),
+            }
+        },
+        MessageAttribute::ResponsePort => {
+            let mut attribute = ResponsePort::new();
+            match attribute.deserialise(body, header) {
+                Ok(_) => AttributeBody::ResponsePort(attribute),
+                Err(error) => return Err(error),
+            }
+        },
+        MessageAttribute::ResponseOrigin => {
+            let mut attribute = ResponseOrigin::new();
+            match attribute.deserialise(body, header) {
+                Ok(_) => AttributeBody::ResponseOrigin(attribute),
+                Err(error) => return Err(error),
+            }
+        },
+        MessageAttribute::OtherAddress => {
+            let mut attribute = OtherAddress::new();
+            match attribute.deserialise(body, header) {
+                Ok(_) => AttributeBody::OtherAddress(attribute),
+                Err(error) => return Err(error),
+            }
+        },
+        MessageAttribute::Priority => {
+            let mut attribute = Priority::new();
+            match attribute.deserialise(body, header) {
+                Ok(_) => AttributeBody::Priority(attribute),
+                Err(error) => return Err(error),
+            }
+        },
+        MessageAttribute::UseCandidate => {
+            let mut attribute = UseCandidate::new();
+            match attribute.deserialise(body, header) {
+                Ok(_) => AttributeBody::UseCandidate(attribute),
+                Err(error) => return Err(error),
             }
         },
+        // Registered types have been decoded already
+        MessageAttribute::Custom(_) => return Err(DecodeError::InvalidValue),
+        MessageAttribute::Unknown(type_code) => AttributeBody::Unknown {
+            type_code,
+            value: body.to_vec(),
+        },
     };
-    
 
-    Ok((attribute_type, attribute, attribute_length))
+    Ok((attribute_type, attribute))
+}
+
+// What an attribute with the given type code decodes as
+pub(crate) fn attribute_type(type_code:u16, registry:Option<&Registry>) -> MessageAttribute {
+    match (builtin(type_code), registry) {
+        (Some(attribute_type), _) => attribute_type,
+        (None, Some(registry)) if registry.is_registered(type_code) => MessageAttribute::Custom(type_code),
+        (None, _) => MessageAttribute::Unknown(type_code),
+    }
+}
+
+// The attribute this crate has a type for with the given type code, if any
+pub(crate) fn builtin(type_code:u16) -> Option<MessageAttribute> {
+    let attribute_type = match type_code {
+        1 => MessageAttribute::MappedAddress,
+        32 => MessageAttribute::XorMappedAddress,
+        8 => MessageAttribute::MessageIntegrity,
+        6 => MessageAttribute::Username,
+        20 => MessageAttribute::Realm,
+        21 => MessageAttribute::Nonce,
+        9 => MessageAttribute::ErrorCode,
+        10 => MessageAttribute::UnknownAttributes,
+        0x8028 => MessageAttribute::Fingerprint,
+        13 => MessageAttribute::Lifetime,
+        22 => MessageAttribute::XorRelayedAddress,
+        25 => MessageAttribute::RequestedTransport,
+        18 => MessageAttribute::XorPeerAddress,
+        19 => MessageAttribute::Data,
+        12 => MessageAttribute::ChannelNumber,
+        42 => MessageAttribute::ConnectionId,
+        3 => MessageAttribute::ChangeRequest,
+        38 => MessageAttribute::Padding,
+        39 => MessageAttribute::ResponsePort,
+        0x802B => MessageAttribute::ResponseOrigin,
+        0x802C => MessageAttribute::OtherAddress,
+        0x0024 => MessageAttribute::Priority,
+        0x0025 => MessageAttribute::UseCandidate,
+        _ => return None,
+    };
+    Some(attribute_type)
 }
 
 pub fn serialise_attribute(attribute:&AttributeBody) -> Vec<u8> {
@@ -175,9 +471,26 @@ pub fn serialise_attribute(attribute:&AttributeBody) -> Vec<u8> {
         AttributeBody::Realm(attribute) => (20, attribute.serialise()),
         AttributeBody::Nonce(attribute) => (21, attribute.serialise()),
         AttributeBody::XorMappedAddress(attribute) => (32, attribute.serialise()),
+        AttributeBody::Fingerprint(attribute) => (0x8028, attribute.serialise()),
+        AttributeBody::Lifetime(attribute) => (13, attribute.serialise()),
+        AttributeBody::XorRelayedAddress(attribute) => (22, attribute.serialise()),
+        AttributeBody::RequestedTransport(attribute) => (25, attribute.serialise()),
+        AttributeBody::XorPeerAddress(attribute) => (18, attribute.serialise()),
+        AttributeBody::Data(attribute) => (19, attribute.serialise()),
+        AttributeBody::ChannelNumber(attribute) => (12, attribute.serialise()),
+        AttributeBody::ConnectionId(attribute) => (42, attribute.serialise()),
+        AttributeBody::ChangeRequest(attribute) => (3, attribute.serialise()),
+        AttributeBody::Padding(attribute) => (38, attribute.serialise()),
+        AttributeBody::ResponsePort(attribute) => (39, attribute.serialise()),
+        AttributeBody::ResponseOrigin(attribute) => (0x802B, attribute.serialise()),
+        AttributeBody::OtherAddress(attribute) => (0x802C, attribute.serialise()),
+        AttributeBody::Priority(attribute) => (0x0024, attribute.serialise()),
+        AttributeBody::UseCandidate(attribute) => (0x0025, attribute.serialise()),
+        AttributeBody::Custom { type_code, value } => (*type_code, value.serialise_value()),
+        AttributeBody::Unknown { type_code, value } => (*type_code, Some(value.clone())),
     } {
-        (attribute_type, Some(attribute_body)) => (attribute_type as u16, attribute_body),
-        (attribute_type, None) => (attribute_type as u16, vec![]),
+        (attribute_type, Some(attribute_body)) => (attribute_type, attribute_body),
+        (attribute_type, None) => (attribute_type, vec![]),
     };
 
     let length = attribute_body.len() as u16;
@@ -187,6 +500,14 @@ pub fn serialise_attribute(attribute:&AttributeBody) -> Vec<u8> {
 
     let mut serialised_attribute = vec![type_bytes[0], type_bytes[1], length_bytes[0], length_bytes[1]];
     serialised_attribute.append(&mut attribute_body);
+    // The length field leaves out the padding
+    serialised_attribute.resize(padded_length(length as usize), 0);
 
     serialised_attribute
 }
+
+// Space an attribute with a value of the given length takes up in a message: its type and
+// length, then the value padded to a multiple of four bytes
+fn padded_length(value_length:usize) -> usize {
+    4 + value_length.div_ceil(4) * 4
+}
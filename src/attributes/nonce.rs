@@ -1,15 +1,20 @@
 use crate::attributes::Attribute;
+use crate::error::DecodeError;
 use crate::header::MessageHeader;
 
 pub struct Nonce {
     nonce: Option<String>,
 }
 impl Nonce {
-    fn with_value(nonce:String) -> Nonce {
+    pub fn with_value(nonce:String) -> Nonce {
         Nonce {
             nonce: Some(nonce),
         }
     }
+
+    pub fn value(&self) -> Option<&str> {
+        self.nonce.as_deref()
+    }
 }
 impl Attribute for Nonce {
     fn new() -> Nonce {
@@ -19,19 +24,16 @@ impl Attribute for Nonce {
     }
 
     fn serialise(&self) -> Option<Vec<u8>> {
-        match &self.nonce {
-            Some(nonce) => Some(nonce.clone().as_bytes().to_vec()),
-            None => None,
-        }
+        self.nonce.as_ref().map(|nonce| nonce.as_bytes().to_vec())
     }
 
-    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), ()> {
+    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), DecodeError> {
        if body.len() > 128 { 
-            return Err(());
+            return Err(DecodeError::TooLong);
        }
        self.nonce = match String::from_utf8(body.to_vec()) {
             Ok(nonce) => Some(nonce),
-            Err(_) => return Err(()),
+            Err(_) => return Err(DecodeError::InvalidUtf8),
        };
        Ok(())
     }
@@ -0,0 +1,36 @@
+use crate::attributes::Attribute;
+use crate::error::DecodeError;
+use crate::attributes::mapped_address::MappedAddress;
+use crate::header::MessageHeader;
+use std::net::SocketAddr;
+
+// Encoded exactly like MAPPED-ADDRESS
+pub struct OtherAddress {
+    address: MappedAddress,
+}
+impl OtherAddress {
+    pub fn with_address(address:SocketAddr) -> OtherAddress {
+        OtherAddress {
+            address: MappedAddress::with_address(address),
+        }
+    }
+
+    pub fn address(&self) -> Option<SocketAddr> {
+        self.address.address()
+    }
+}
+impl Attribute for OtherAddress {
+    fn new() -> OtherAddress {
+        OtherAddress {
+            address: MappedAddress::new(),
+        }
+    }
+
+    fn serialise(&self) -> Option<Vec<u8>> {
+        self.address.serialise()
+    }
+
+    fn deserialise(&mut self, body:&[u8], header:&MessageHeader) -> Result<(), DecodeError> {
+        self.address.deserialise(body, header)
+    }
+}
@@ -0,0 +1,35 @@
+use crate::attributes::Attribute;
+use crate::error::DecodeError;
+use crate::header::MessageHeader;
+
+// Only the length of the padding matters, its contents are ignored
+pub struct Padding {
+    length: Option<usize>,
+}
+impl Padding {
+    pub fn with_length(length:usize) -> Padding {
+        Padding {
+            length: Some(length),
+        }
+    }
+
+    pub fn length(&self) -> Option<usize> {
+        self.length
+    }
+}
+impl Attribute for Padding {
+    fn new() -> Padding {
+        Padding {
+            length: None,
+        }
+    }
+
+    fn serialise(&self) -> Option<Vec<u8>> {
+        self.length.map(|length| vec![0; length])
+    }
+
+    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), DecodeError> {
+        self.length = Some(body.len());
+        Ok(())
+    }
+}
@@ -0,0 +1,41 @@
+use crate::attributes::Attribute;
+use crate::error::DecodeError;
+use crate::header::MessageHeader;
+
+// ICE candidate priority (RFC 8445 section 7.1.1), carried in Binding requests used as
+// connectivity checks. The server has no use for it beyond recognising it.
+pub struct Priority {
+    priority: Option<u32>,
+}
+impl Priority {
+    pub fn with_value(priority:u32) -> Priority {
+        Priority {
+            priority: Some(priority),
+        }
+    }
+
+    pub fn value(&self) -> Option<u32> {
+        self.priority
+    }
+}
+impl Attribute for Priority {
+    fn new() -> Priority {
+        Priority {
+            priority: None,
+        }
+    }
+
+    fn serialise(&self) -> Option<Vec<u8>> {
+        self.priority.map(|priority| priority.to_be_bytes().to_vec())
+    }
+
+    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), DecodeError> {
+        if body.len() != 4 {
+            return Err(DecodeError::BadLength);
+        }
+
+        self.priority = Some(u32::from_be_bytes([body[0], body[1], body[2], body[3]]));
+
+        Ok(())
+    }
+}
@@ -1,15 +1,20 @@
 use crate::attributes::Attribute;
+use crate::error::DecodeError;
 use crate::header::MessageHeader;
 
 pub struct Realm {
     realm: Option<String>,
 }
 impl Realm {
-    fn with_value(realm:String) -> Realm {
+    pub fn with_value(realm:String) -> Realm {
         Realm {
             realm: Some(realm),
         }
     }
+
+    pub fn value(&self) -> Option<&str> {
+        self.realm.as_deref()
+    }
 }
 impl Attribute for Realm {
     fn new() -> Realm {
@@ -19,19 +24,16 @@ impl Attribute for Realm {
     }
 
     fn serialise(&self) -> Option<Vec<u8>> {
-        match &self.realm {
-            Some(realm) => Some(realm.clone().as_bytes().to_vec()),
-            None => None,
-        }
+        self.realm.as_ref().map(|realm| realm.as_bytes().to_vec())
     }
 
-    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), ()> {
+    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), DecodeError> {
        if body.len() > 128 { 
-            return Err(());
+            return Err(DecodeError::TooLong);
        }
        self.realm = match String::from_utf8(body.to_vec()) {
             Ok(realm) => Some(realm),
-            Err(_) => return Err(()),
+            Err(_) => return Err(DecodeError::InvalidUtf8),
        };
        Ok(())
     }
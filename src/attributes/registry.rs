@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use crate::attributes::{ self, Attribute, CustomAttribute };
+use crate::error::{ DecodeError, RegistryError };
+use crate::header::MessageHeader;
+
+type Decoder = fn(&[u8], &MessageHeader) -> Result<Box<dyn CustomAttribute>, DecodeError>;
+
+// Attribute types registered by an application, by type code. Messages decoded with a
+// registry decode these attributes into the registered types.
+#[derive(Default)]
+pub struct Registry {
+    decoders: HashMap<u16, Decoder>,
+}
+impl Registry {
+    pub fn new() -> Registry {
+        Registry {
+            decoders: HashMap::new(),
+        }
+    }
+
+    // Decodes attributes with the given type code as T from now on. Fails if the code
+    // belongs to an attribute this crate already has a type for, or is registered already.
+    pub fn register<T: Attribute + Send + Sync + 'static>(&mut self, type_code:u16) -> Result<(), RegistryError> {
+        if attributes::builtin(type_code).is_some() {
+            return Err(RegistryError::Builtin(type_code));
+        }
+        if self.decoders.contains_key(&type_code) {
+            return Err(RegistryError::AlreadyRegistered(type_code));
+        }
+
+        self.decoders.insert(type_code, decode::<T>);
+        Ok(())
+    }
+
+    pub fn unregister(&mut self, type_code:u16) {
+        self.decoders.remove(&type_code);
+    }
+
+    pub fn is_registered(&self, type_code:u16) -> bool {
+        self.decoders.contains_key(&type_code)
+    }
+
+    // None if nothing is registered for the type code
+    pub(crate) fn decode(&self, type_code:u16, body:&[u8], header:&MessageHeader) -> Option<Result<Box<dyn CustomAttribute>, DecodeError>> {
+        let decoder = self.decoders.get(&type_code)?;
+        Some(decoder(body, header))
+    }
+}
+
+fn decode<T: Attribute + Send + Sync + 'static>(body:&[u8], header:&MessageHeader) -> Result<Box<dyn CustomAttribute>, DecodeError> {
+    let mut attribute = T::new();
+    attribute.deserialise(body, header)?;
+    Ok(Box::new(attribute))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attributes::{ AttributeBody, MessageAttribute };
+    use crate::header::{ self, MessageClass, MessageType };
+    use crate::message::{ Message, MessageBuilder };
+
+    struct Raw(Vec<u8>);
+    impl Attribute for Raw {
+        fn new() -> Self {
+            Raw(vec![])
+        }
+
+        fn serialise(&self) -> Option<Vec<u8>> {
+            Some(self.0.clone())
+        }
+
+        fn deserialise(&mut self, body:&[u8], _:&MessageHeader) -> Result<(), DecodeError> {
+            self.0 = body.to_vec();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn register_rejects_builtin_and_taken_codes() {
+        let mut registry = Registry::new();
+        assert_eq!(registry.register::<Raw>(0x0006), Err(RegistryError::Builtin(0x0006)));
+        assert_eq!(registry.register::<Raw>(0xC001), Ok(()));
+        assert_eq!(registry.register::<Raw>(0xC001), Err(RegistryError::AlreadyRegistered(0xC001)));
+        registry.unregister(0xC001);
+        assert_eq!(registry.register::<Raw>(0xC001), Ok(()));
+    }
+
+    #[test]
+    fn registries_are_independent() {
+        let mut registry = Registry::new();
+        registry.register::<Raw>(0xC002).unwrap();
+        assert!(registry.is_registered(0xC002));
+        assert!(!Registry::new().is_registered(0xC002));
+    }
+
+    // A comprehension-required attribute with no built-in type, padded on the wire
+    const COMPREHENSION_REQUIRED: u16 = 0x0030;
+
+    fn message() -> Vec<u8> {
+        MessageBuilder::new(MessageType::Binding, MessageClass::Request)
+            .attribute(AttributeBody::Unknown {
+                type_code: COMPREHENSION_REQUIRED,
+                value: b"abc".to_vec(),
+            })
+            .build()
+    }
+
+    fn registry() -> Registry {
+        let mut registry = Registry::new();
+        registry.register::<Raw>(COMPREHENSION_REQUIRED).unwrap();
+        registry
+    }
+
+    #[test]
+    fn registered_attributes_decode_into_their_type() {
+        let message = message();
+        let registry = registry();
+
+        let decoded = Message::decode_with(&message, &registry).unwrap();
+        match decoded.get(MessageAttribute::Custom(COMPREHENSION_REQUIRED)) {
+            Ok(Some(attribute)) => {
+                assert_eq!(attribute.custom::<Raw>().map(|raw| &raw.0[..]), Some(&b"abc"[..]));
+                assert!(attribute.custom::<String>().is_none());
+            },
+            _ => panic!("not decoded"),
+        }
+
+        let header = header::verify_header(&message[0..20]).unwrap();
+        let (attributes, _) = attributes::get_attributes_with(&message[20..], &header, &registry).unwrap();
+        let attribute = attributes.get(MessageAttribute::Custom(COMPREHENSION_REQUIRED)).unwrap();
+        assert_eq!(attribute.custom::<Raw>().map(|raw| &raw.0[..]), Some(&b"abc"[..]));
+
+        // Without the registry it is only known by its type code
+        let (attributes, _) = attributes::get_attributes(&message[20..], &header).unwrap();
+        assert!(matches!(attributes.get(MessageAttribute::Unknown(COMPREHENSION_REQUIRED)), Some(AttributeBody::Unknown { .. })));
+    }
+
+    #[test]
+    fn registered_attributes_are_not_unknown() {
+        let message = message();
+        let registry = registry();
+        assert!(!Message::decode(&message).unwrap().unknown_attributes().is_empty());
+        assert!(Message::decode_with(&message, &registry).unwrap().unknown_attributes().is_empty());
+
+        let header = header::verify_header(&message[0..20]).unwrap();
+        let (_, unknown_attributes) = attributes::get_attributes(&message[20..], &header).unwrap();
+        assert!(!unknown_attributes.is_empty());
+        let (_, unknown_attributes) = attributes::get_attributes_with(&message[20..], &header, &registry).unwrap();
+        assert!(unknown_attributes.is_empty());
+    }
+
+    #[test]
+    fn registered_attributes_serialise_as_received() {
+        let message = message();
+        let registry = registry();
+        let attribute = Message::decode_with(&message, &registry).unwrap().get(MessageAttribute::Custom(COMPREHENSION_REQUIRED)).unwrap().unwrap();
+        assert_eq!(attributes::serialise_attribute(&attribute), &message[20..]);
+
+        let rebuilt = MessageBuilder::new(MessageType::Binding, MessageClass::Request)
+            .transaction_id(&Message::decode(&message).unwrap().header.id)
+            .attribute(attribute)
+            .build();
+        assert_eq!(rebuilt, message);
+    }
+}
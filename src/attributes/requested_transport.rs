@@ -0,0 +1,43 @@
+use crate::attributes::Attribute;
+use crate::error::DecodeError;
+use crate::header::MessageHeader;
+
+// IANA protocol numbers
+pub const TCP: u8 = 6;
+pub const UDP: u8 = 17;
+
+pub struct RequestedTransport {
+    protocol: Option<u8>,
+}
+impl RequestedTransport {
+    pub fn with_protocol(protocol:u8) -> RequestedTransport {
+        RequestedTransport {
+            protocol: Some(protocol),
+        }
+    }
+
+    pub fn protocol(&self) -> Option<u8> {
+        self.protocol
+    }
+}
+impl Attribute for RequestedTransport {
+    fn new() -> RequestedTransport {
+        RequestedTransport {
+            protocol: None,
+        }
+    }
+
+    fn serialise(&self) -> Option<Vec<u8>> {
+        self.protocol.map(|protocol| vec![protocol, 0, 0, 0])
+    }
+
+    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), DecodeError> {
+        if body.len() != 4 {
+            return Err(DecodeError::BadLength);
+        }
+
+        self.protocol = Some(body[0]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,42 @@
+use crate::attributes::Attribute;
+use crate::error::DecodeError;
+use crate::header::MessageHeader;
+
+pub struct ResponsePort {
+    port: Option<u16>,
+}
+impl ResponsePort {
+    pub fn with_value(port:u16) -> ResponsePort {
+        ResponsePort {
+            port: Some(port),
+        }
+    }
+
+    pub fn value(&self) -> Option<u16> {
+        self.port
+    }
+}
+impl Attribute for ResponsePort {
+    fn new() -> ResponsePort {
+        ResponsePort {
+            port: None,
+        }
+    }
+
+    fn serialise(&self) -> Option<Vec<u8>> {
+        self.port.map(|port| {
+            let bytes = port.to_be_bytes();
+            vec![bytes[0], bytes[1], 0, 0]
+        })
+    }
+
+    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), DecodeError> {
+        if body.len() != 4 {
+            return Err(DecodeError::BadLength);
+        }
+
+        self.port = Some(u16::from_be_bytes([body[0], body[1]]));
+
+        Ok(())
+    }
+}
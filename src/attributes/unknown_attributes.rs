@@ -1,4 +1,5 @@
 use crate::attributes::Attribute;
+use crate::error::DecodeError;
 use crate::header::MessageHeader;
 
 pub struct UnknownAttributes {
@@ -16,6 +17,13 @@ impl UnknownAttributes {
             attributes.push(attribute);
         }
     }
+
+    pub fn is_empty(&self) -> bool {
+        match &self.attributes {
+            Some(attributes) => attributes.is_empty(),
+            None => true,
+        }
+    }
 }
 impl Attribute for UnknownAttributes {
     fn new() -> UnknownAttributes {
@@ -40,9 +48,9 @@ impl Attribute for UnknownAttributes {
         Some(buf)
     }
 
-    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), ()> {
-        if body.len() % 2 != 0 {
-            return Err(());
+    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), DecodeError> {
+        if !body.len().is_multiple_of(2) {
+            return Err(DecodeError::BadLength);
         }
 
         let mut attributes = vec![];
@@ -0,0 +1,22 @@
+use crate::attributes::Attribute;
+use crate::error::DecodeError;
+use crate::header::MessageHeader;
+
+// ICE nomination flag (RFC 8445 section 7.1.2). It has no value; being there is all it says.
+pub struct UseCandidate;
+impl Attribute for UseCandidate {
+    fn new() -> UseCandidate {
+        UseCandidate
+    }
+
+    fn serialise(&self) -> Option<Vec<u8>> {
+        Some(vec![])
+    }
+
+    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), DecodeError> {
+        if !body.is_empty() {
+            return Err(DecodeError::BadLength);
+        }
+        Ok(())
+    }
+}
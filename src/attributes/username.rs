@@ -1,15 +1,20 @@
 use crate::attributes::Attribute;
+use crate::error::DecodeError;
 use crate::header::MessageHeader;
 
 pub struct Username {
     username: Option<String>,
 }
 impl Username {
-    fn with_value(username:String) -> Username {
+    pub fn with_value(username:String) -> Username {
         Username {
             username: Some(username),
         }
     }
+
+    pub fn value(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
 }
 impl Attribute for Username {
     fn new() -> Username {
@@ -19,19 +24,16 @@ impl Attribute for Username {
     }
 
     fn serialise(&self) -> Option<Vec<u8>> {
-        match &self.username {
-            Some(username) => Some(username.clone().as_bytes().to_vec()),
-            None => None,
-        }
+        self.username.as_ref().map(|username| username.as_bytes().to_vec())
     }
 
-    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), ()> {
+    fn deserialise(&mut self, body:&[u8], _header:&MessageHeader) -> Result<(), DecodeError> {
        if body.len() > 512 { 
-            return Err(());
+            return Err(DecodeError::TooLong);
        }
        self.username = match String::from_utf8(body.to_vec()) {
             Ok(username) => Some(username),
-            Err(_) => return Err(()),
+            Err(_) => return Err(DecodeError::InvalidUtf8),
        };
        Ok(())
     }
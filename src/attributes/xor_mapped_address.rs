@@ -1,4 +1,5 @@
 use crate::attributes::Attribute;
+use crate::error::{ AddressKeyError, DecodeError };
 use crate::header::MessageHeader;
 use std::net::{ IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr };
 
@@ -7,17 +8,33 @@ pub struct XorMappedAddress {
     address_key: Vec<u8>,
 }
 impl XorMappedAddress {  
-    pub fn with_address(address:SocketAddr, key:Vec<u8>) -> Result<XorMappedAddress, ()> {
-        if address.is_ipv4() && key.len() != 4 {
-            Err(())
-        } else if address.is_ipv6() && key.len() != 16 {
-            Err(())
-        } else {
-            Ok(XorMappedAddress {
-                address: Some(address),
-                address_key: key,
-            })
+    pub fn with_address(address:SocketAddr, key:Vec<u8>) -> Result<XorMappedAddress, AddressKeyError> {
+        let expected = match address {
+            SocketAddr::V4(_) => 4,
+            SocketAddr::V6(_) => 16,
+        };
+        if key.len() != expected {
+            return Err(AddressKeyError {
+                expected,
+                actual: key.len(),
+            });
         }
+        Ok(XorMappedAddress {
+            address: Some(address),
+            address_key: key,
+        })
+    }
+
+    pub fn address(&self) -> Option<SocketAddr> {
+        self.address
+    }
+}
+
+// IPv4 addresses are XORed with the magic cookie, IPv6 with the cookie and transaction ID
+pub fn address_key(address:&SocketAddr, id:&[u8; 12]) -> Vec<u8> {
+    match address.ip() {
+        IpAddr::V4(_) => vec![0x21, 0x12, 0xA4, 0x42],
+        IpAddr::V6(_) => [0x21, 0x12, 0xA4, 0x42].iter().chain(id).copied().collect::<Vec<u8>>(),
     }
 }
 impl Attribute for XorMappedAddress {
@@ -29,10 +46,7 @@ impl Attribute for XorMappedAddress {
     }
 
     fn serialise(&self) -> Option<Vec<u8>>{
-        let address = match self.address {
-            Some(address) => address,
-            None => return None,
-        };
+        let address = self.address?;
 
         let family = if address.is_ipv4() {
             1
@@ -54,41 +68,33 @@ impl Attribute for XorMappedAddress {
                              .map(|(octet, key)| octet ^ key)
                              .collect::<Vec<u8>>();
 
-        let buf = vec![0, family, p1, p2].iter()
-                                         .chain(&address)
-                                         .map(|b| *b)
-                                         .collect::<Vec<u8>>();
+        let buf = [0, family, p1, p2].iter()
+                                     .chain(&address)
+                                     .copied()
+                                     .collect::<Vec<u8>>();
         Some(buf)
     }
 
-    fn deserialise(&mut self, body:&[u8], header:&MessageHeader) -> Result<(), ()> {
+    fn deserialise(&mut self, body:&[u8], header:&MessageHeader) -> Result<(), DecodeError> {
+        if body.len() < 4 {
+            return Err(DecodeError::Truncated);
+        }
         let port = u16::from_be_bytes([body[2] ^ 0x21, body[3] ^ 0x12]);
         let address_key = match body[1] {
             1 => vec![0x21, 0x12, 0xA4, 0x42],
-            2 => vec![0x21, 0x12, 0xA4, 0x42].iter().chain(&header.id).map(|b| *b).collect::<Vec<u8>>(),
-            _ => vec![],
+            2 => [0x21, 0x12, 0xA4, 0x42].iter().chain(&header.id).copied().collect::<Vec<u8>>(),
+            family => return Err(DecodeError::BadFamily(family)),
         };
         let address = match (body[1], body.len()) {
-            (1, 8) => {
-                if body.len() < 8 {
-                    return Err(());
-                }
-                SocketAddr::new(IpAddr::V4(Ipv4Addr::new(body[4] ^ 0x21, body[5] ^ 0x12, body[6] ^ 0xA4, body[7] ^ 0x42)), port)
-            },
+            (1, 8) => SocketAddr::new(IpAddr::V4(Ipv4Addr::new(body[4] ^ 0x21, body[5] ^ 0x12, body[6] ^ 0xA4, body[7] ^ 0x42)), port),
             (2, 20) => {
-                if body.len() < 20 {
-                    return Err(());
+                let mut octets = [0; 16];
+                for (octet, (byte, key)) in octets.iter_mut().zip(body[4..20].iter().zip(&address_key)) {
+                    *octet = byte ^ key;
                 }
-                let address_bits = body.chunks(2)
-                                       .skip(2)
-                                       .zip(address_key.chunks(2))
-                                       .map(|(address_chunk, key_chunk)| {
-                                            u16::from_be_bytes([address_chunk[0] ^ key_chunk[0], address_chunk[1] ^ key_chunk[1]])
-                                       })
-                                       .collect::<Vec<u16>>();
-                SocketAddr::new(IpAddr::V6(Ipv6Addr::new(address_bits[0], address_bits[1], address_bits[2], address_bits[3], address_bits[4], address_bits[5], address_bits[6], address_bits[7])), port)
+                SocketAddr::new(IpAddr::V6(Ipv6Addr::from(octets)), port)
             },
-            _ => return Err(()),
+            _ => return Err(DecodeError::BadLength),
         };
         self.address = Some(address);
         self.address_key = address_key;
@@ -0,0 +1,36 @@
+use crate::attributes::Attribute;
+use crate::error::{ AddressKeyError, DecodeError };
+use crate::attributes::xor_mapped_address::XorMappedAddress;
+use crate::header::MessageHeader;
+use std::net::SocketAddr;
+
+// Encoded exactly like XOR-MAPPED-ADDRESS
+pub struct XorPeerAddress {
+    address: XorMappedAddress,
+}
+impl XorPeerAddress {
+    pub fn with_address(address:SocketAddr, key:Vec<u8>) -> Result<XorPeerAddress, AddressKeyError> {
+        XorMappedAddress::with_address(address, key).map(|address| XorPeerAddress {
+            address,
+        })
+    }
+
+    pub fn address(&self) -> Option<SocketAddr> {
+        self.address.address()
+    }
+}
+impl Attribute for XorPeerAddress {
+    fn new() -> XorPeerAddress {
+        XorPeerAddress {
+            address: XorMappedAddress::new(),
+        }
+    }
+
+    fn serialise(&self) -> Option<Vec<u8>> {
+        self.address.serialise()
+    }
+
+    fn deserialise(&mut self, body:&[u8], header:&MessageHeader) -> Result<(), DecodeError> {
+        self.address.deserialise(body, header)
+    }
+}
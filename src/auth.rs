@@ -0,0 +1,337 @@
+extern crate base64;
+extern crate hmac;
+extern crate md5;
+extern crate rand;
+extern crate sha1;
+
+use std::collections::HashMap;
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use hmac::{ Hmac, Mac };
+use md5::{ Digest, Md5 };
+use sha1::Sha1;
+
+use crate::attributes::{ Attributes, MessageAttribute, AttributeBody };
+use crate::attributes::error_code::ErrorCodeType;
+
+// Seconds a nonce handed out in a 401 remains valid
+const NONCE_LIFETIME: u64 = 3600;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum Mechanism {
+    LongTerm,
+    ShortTerm,
+}
+
+pub struct Authenticator {
+    mechanism: Mechanism,
+    realm: String,
+    users: HashMap<String, String>,
+    // Secrets shared with whatever issues TURN REST API credentials, any of which is accepted
+    // so that they can be rotated
+    shared_secrets: Vec<String>,
+    secret: [u8; 20],
+}
+impl Authenticator {
+    pub fn new(mechanism:Mechanism, realm:String, users:HashMap<String, String>, shared_secrets:Vec<String>) -> Authenticator {
+        Authenticator {
+            mechanism,
+            realm,
+            users,
+            shared_secrets,
+            secret: rand::random(),
+        }
+    }
+
+    pub fn mechanism(&self) -> Mechanism {
+        self.mechanism
+    }
+
+    pub fn realm(&self) -> &str {
+        &self.realm
+    }
+
+    // Nonces are stateless: the issue time followed by a MAC of it under the server secret
+    pub fn generate_nonce(&self) -> String {
+        let timestamp = now();
+        format!("{:016x}{}", timestamp, self.nonce_signature(timestamp))
+    }
+
+    // Authentication is on once there are credentials to check against
+    pub fn enabled(&self) -> bool {
+        !self.users.is_empty() || !self.shared_secrets.is_empty()
+    }
+
+    // Returns the key to protect the response with, or None if authentication is disabled
+    pub fn authenticate(&self, message:&[u8], attributes:&Attributes) -> Result<Option<Vec<u8>>, ErrorCodeType> {
+        if !self.enabled() {
+            return Ok(None);
+        }
+
+        match self.mechanism {
+            Mechanism::LongTerm => self.authenticate_long_term(message, attributes),
+            Mechanism::ShortTerm => self.authenticate_short_term(message, attributes),
+        }
+    }
+
+    fn authenticate_long_term(&self, message:&[u8], attributes:&Attributes) -> Result<Option<Vec<u8>>, ErrorCodeType> {
+        let integrity = match attributes.get(MessageAttribute::MessageIntegrity) {
+            Some(AttributeBody::MessageIntegrity(integrity)) => integrity,
+            _ => return Err(ErrorCodeType::Unauthorised),
+        };
+        let username = match attributes.get(MessageAttribute::Username) {
+            Some(AttributeBody::Username(username)) => username.value(),
+            _ => None,
+        };
+        let realm = match attributes.get(MessageAttribute::Realm) {
+            Some(AttributeBody::Realm(realm)) => realm.value(),
+            _ => None,
+        };
+        let nonce = match attributes.get(MessageAttribute::Nonce) {
+            Some(AttributeBody::Nonce(nonce)) => nonce.value(),
+            _ => None,
+        };
+        let (username, _realm, nonce) = match (username, realm, nonce) {
+            (Some(username), Some(realm), Some(nonce)) => (username, realm, nonce),
+            _ => return Err(ErrorCodeType::BadRequest),
+        };
+
+        if !self.verify_nonce(nonce) {
+            return Err(ErrorCodeType::StaleNonce);
+        }
+
+        match self.passwords(username).iter()
+                                      .map(|password| long_term_key(username, &self.realm, password))
+                                      .find(|key| integrity.verify(message, key)) {
+            Some(key) => Ok(Some(key)),
+            None => Err(ErrorCodeType::Unauthorised),
+        }
+    }
+
+    // RFC 5389 10.1: the password itself is the key and there is no realm or nonce
+    fn authenticate_short_term(&self, message:&[u8], attributes:&Attributes) -> Result<Option<Vec<u8>>, ErrorCodeType> {
+        let integrity = attributes.get(MessageAttribute::MessageIntegrity);
+        let username = attributes.get(MessageAttribute::Username);
+        let (integrity, username) = match (integrity, username) {
+            (Some(AttributeBody::MessageIntegrity(integrity)), Some(AttributeBody::Username(username))) => (integrity, username),
+            _ => return Err(ErrorCodeType::BadRequest),
+        };
+
+        let passwords = match username.value() {
+            Some(username) => self.passwords(username),
+            None => return Err(ErrorCodeType::Unauthorised),
+        };
+        match passwords.into_iter()
+                       .map(|password| password.into_bytes())
+                       .find(|key| integrity.verify(message, key)) {
+            Some(key) => Ok(Some(key)),
+            None => Err(ErrorCodeType::Unauthorised),
+        }
+    }
+
+    // Every password the user might have: the configured one, and one per shared secret if the
+    // username is an unexpired TURN REST API username
+    fn passwords(&self, username:&str) -> Vec<String> {
+        let mut passwords = vec![];
+        if let Some(password) = self.users.get(username) {
+            passwords.push(password.clone());
+        }
+        if rest_expiry(username).is_some_and(|expiry| expiry >= now()) {
+            passwords.extend(self.shared_secrets.iter().filter_map(|secret| rest_password(secret, username)));
+        }
+        passwords
+    }
+
+    fn verify_nonce(&self, nonce:&str) -> bool {
+        if nonce.len() != 32 || !nonce.is_ascii() {
+            return false;
+        }
+        let timestamp = match u64::from_str_radix(&nonce[0..16], 16) {
+            Ok(timestamp) => timestamp,
+            Err(_) => return false,
+        };
+        if now().saturating_sub(timestamp) > NONCE_LIFETIME {
+            return false;
+        }
+
+        nonce[16..] == self.nonce_signature(timestamp)
+    }
+
+    fn nonce_signature(&self, timestamp:u64) -> String {
+        let mut mac = match Hmac::<Sha1>::new_from_slice(&self.secret) {
+            Ok(mac) => mac,
+            Err(_) => return String::new(),
+        };
+        mac.update(&timestamp.to_be_bytes());
+        mac.finalize()
+           .into_bytes()
+           .iter()
+           .take(8)
+           .map(|b| format!("{:02x}", b))
+           .collect::<String>()
+    }
+}
+
+// TURN REST API usernames are "expiry:userid", expiry being a Unix timestamp
+fn rest_expiry(username:&str) -> Option<u64> {
+    let (expiry, _userid) = username.split_once(':')?;
+    if expiry.is_empty() || !expiry.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    expiry.parse::<u64>().ok()
+}
+
+// password = base64(HMAC-SHA1(secret, username))
+fn rest_password(secret:&str, username:&str) -> Option<String> {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret.as_bytes()).ok()?;
+    mac.update(username.as_bytes());
+    Some(STANDARD.encode(mac.finalize().into_bytes()))
+}
+
+// key = MD5(username ":" realm ":" password)
+fn long_term_key(username:&str, realm:&str, password:&str) -> Vec<u8> {
+    let mut hasher = Md5::new();
+    hasher.update(format!("{}:{}:{}", username, realm, password).as_bytes());
+    hasher.finalize().to_vec()
+}
+
+fn now() -> u64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs(),
+        Err(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::attributes;
+    use crate::attributes::nonce::Nonce;
+    use crate::attributes::realm::Realm;
+    use crate::attributes::username::Username;
+    use crate::header::{ self, MessageClass, MessageType };
+    use crate::message::MessageBuilder;
+
+    fn authenticator(mechanism:Mechanism) -> Authenticator {
+        let mut users = HashMap::new();
+        users.insert("alice".to_string(), "secret".to_string());
+        Authenticator::new(mechanism, "example.org".to_string(), users, vec![])
+    }
+
+    // Both shared secrets of a rotation, and no static users
+    fn rest_authenticator(mechanism:Mechanism) -> Authenticator {
+        Authenticator::new(mechanism, "example.org".to_string(), HashMap::new(), vec!["old".to_string(), "new".to_string()])
+    }
+
+    fn request(username:&str, nonce:&str, password:&str) -> Vec<u8> {
+        MessageBuilder::new(MessageType::Allocate, MessageClass::Request)
+            .attribute(AttributeBody::Username(Username::with_value(username.to_string())))
+            .attribute(AttributeBody::Realm(Realm::with_value("example.org".to_string())))
+            .attribute(AttributeBody::Nonce(Nonce::with_value(nonce.to_string())))
+            .message_integrity(&long_term_key(username, "example.org", password))
+            .build()
+    }
+
+    fn short_term_request(username:&str, password:&str) -> Vec<u8> {
+        MessageBuilder::new(MessageType::Binding, MessageClass::Request)
+            .attribute(AttributeBody::Username(Username::with_value(username.to_string())))
+            .message_integrity(password.as_bytes())
+            .build()
+    }
+
+    fn authenticate(authenticator:&Authenticator, message:&[u8]) -> Result<Option<Vec<u8>>, ErrorCodeType> {
+        let header = header::verify_header(&message[0..20]).unwrap();
+        let (attributes, _) = attributes::get_attributes(&message[20..], &header).unwrap();
+        authenticator.authenticate(message, &attributes)
+    }
+
+    #[test]
+    fn nonce_flow() {
+        let authenticator = authenticator(Mechanism::LongTerm);
+
+        // The first request carries no credentials and is answered with a 401 holding a nonce
+        let message = MessageBuilder::new(MessageType::Allocate, MessageClass::Request).build();
+        assert!(authenticate(&authenticator, &message) == Err(ErrorCodeType::Unauthorised));
+
+        let nonce = authenticator.generate_nonce();
+        let key = long_term_key("alice", "example.org", "secret");
+        assert!(authenticate(&authenticator, &request("alice", &nonce, "secret")) == Ok(Some(key)));
+        assert!(authenticate(&authenticator, &request("alice", &nonce, "wrong")) == Err(ErrorCodeType::Unauthorised));
+
+        // Once the nonce has expired the request gets a 438, and a new nonce with it
+        let issued = now() - NONCE_LIFETIME - 1;
+        let expired = format!("{:016x}{}", issued, authenticator.nonce_signature(issued));
+        assert!(authenticate(&authenticator, &request("alice", &expired, "secret")) == Err(ErrorCodeType::StaleNonce));
+    }
+
+    #[test]
+    fn nonces_from_elsewhere_are_stale() {
+        let authenticator = authenticator(Mechanism::LongTerm);
+        let nonce = Authenticator::new(Mechanism::LongTerm, "example.org".to_string(), HashMap::new(), vec![]).generate_nonce();
+        assert!(authenticate(&authenticator, &request("alice", &nonce, "secret")) == Err(ErrorCodeType::StaleNonce));
+        assert!(authenticate(&authenticator, &request("alice", "f//499k954d6OL34oL9FSTvy64sA", "secret")) == Err(ErrorCodeType::StaleNonce));
+    }
+
+    #[test]
+    fn rest_password_is_base64_hmac_sha1_of_the_username() {
+        assert_eq!(rest_password("north", "1700000000:alice"), Some("Cd/49soE35ICqcJF/bCTn8Z4OyE=".to_string()));
+    }
+
+    #[test]
+    fn rest_credentials_from_either_shared_secret_are_accepted() {
+        let authenticator = rest_authenticator(Mechanism::LongTerm);
+        let username = format!("{}:alice", now() + 3600);
+        let nonce = authenticator.generate_nonce();
+        for secret in ["old", "new"] {
+            let password = rest_password(secret, &username).unwrap();
+            let key = long_term_key(&username, "example.org", &password);
+            assert!(authenticate(&authenticator, &request(&username, &nonce, &password)) == Ok(Some(key)));
+        }
+
+        let password = rest_password("wrong", &username).unwrap();
+        assert!(authenticate(&authenticator, &request(&username, &nonce, &password)) == Err(ErrorCodeType::Unauthorised));
+    }
+
+    #[test]
+    fn expired_or_malformed_rest_usernames_are_rejected() {
+        let authenticator = rest_authenticator(Mechanism::LongTerm);
+        let nonce = authenticator.generate_nonce();
+        let expired = format!("{}:alice", now() - 1);
+        for username in [expired.as_str(), "alice", ":alice", "tomorrow:alice", "+4000000000:alice", "4000000000x:alice"] {
+            let password = rest_password("old", username).unwrap();
+            assert!(authenticate(&authenticator, &request(username, &nonce, &password)) == Err(ErrorCodeType::Unauthorised), "{}", username);
+        }
+    }
+
+    #[test]
+    fn rest_credentials_work_under_the_short_term_mechanism() {
+        let authenticator = rest_authenticator(Mechanism::ShortTerm);
+        let username = format!("{}:alice", now() + 3600);
+        let password = rest_password("new", &username).unwrap();
+        assert!(authenticate(&authenticator, &short_term_request(&username, &password)) == Ok(Some(password.into_bytes())));
+        assert!(authenticate(&authenticator, &short_term_request(&username, "wrong")) == Err(ErrorCodeType::Unauthorised));
+
+        let expired = format!("{}:alice", now() - 1);
+        let password = rest_password("new", &expired).unwrap();
+        assert!(authenticate(&authenticator, &short_term_request(&expired, &password)) == Err(ErrorCodeType::Unauthorised));
+    }
+
+    #[test]
+    fn short_term_credentials() {
+        let authenticator = authenticator(Mechanism::ShortTerm);
+        assert!(authenticate(&authenticator, &short_term_request("alice", "secret")) == Ok(Some(b"secret".to_vec())));
+
+        // USERNAME and MESSAGE-INTEGRITY are both needed, but nothing else is
+        let message = MessageBuilder::new(MessageType::Binding, MessageClass::Request).message_integrity(b"secret").build();
+        assert!(authenticate(&authenticator, &message) == Err(ErrorCodeType::BadRequest));
+        let message = MessageBuilder::new(MessageType::Binding, MessageClass::Request)
+            .attribute(AttributeBody::Username(Username::with_value("alice".to_string())))
+            .build();
+        assert!(authenticate(&authenticator, &message) == Err(ErrorCodeType::BadRequest));
+
+        assert!(authenticate(&authenticator, &short_term_request("bob", "secret")) == Err(ErrorCodeType::Unauthorised));
+        assert!(authenticate(&authenticator, &short_term_request("alice", "wrong")) == Err(ErrorCodeType::Unauthorised));
+    }
+}
@@ -0,0 +1,206 @@
+// Classifies the NAT between this host and a server running RFC 5780 NAT
+// behaviour discovery, following the tests in RFC 5780 section 4
+extern crate rand;
+extern crate stun;
+
+use std::env;
+use std::net::{ IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket };
+use std::process;
+use std::time::Duration;
+
+use stun::attributes::{ MessageAttribute, AttributeBody };
+use stun::attributes::change_request::ChangeRequest;
+use stun::client::{ self, Timers };
+use stun::header::MessageClass;
+use stun::message::Message;
+
+// Gives up sooner than RFC 5389 would, since an unanswered request is an expected
+// outcome of the filtering tests
+const TEST_TIMERS: Timers = Timers {
+    rto: Duration::from_millis(500),
+    rc: 3,
+    rm: 4,
+};
+
+struct BindingResponse {
+    mapped: SocketAddr,
+    other: Option<SocketAddr>,
+    // Where the response actually came from
+    origin: SocketAddr,
+}
+
+fn main() {
+    let args = env::args().collect::<Vec<String>>();
+    if args.len() < 2 {
+        eprintln!("usage: {} <server:port> [local address:port]", args[0]);
+        process::exit(1);
+    }
+    let server = match args[1].to_socket_addrs().map(|mut addresses| addresses.next()) {
+        Ok(Some(server)) => server,
+        _ => {
+            eprintln!("invalid server address {}", args[1]);
+            process::exit(1);
+        },
+    };
+    let local = match args.get(2) {
+        Some(local) => match local.parse::<SocketAddr>() {
+            Ok(local) => local,
+            Err(_) => {
+                eprintln!("invalid local address {}", local);
+                process::exit(1);
+            },
+        },
+        None => SocketAddr::new(unspecified(&server), 0),
+    };
+    let socket = match UdpSocket::bind(local) {
+        Ok(socket) => socket,
+        Err(_) => {
+            eprintln!("unable to establish udp socket at {}", local);
+            process::exit(1);
+        },
+    };
+
+    // Test I: a plain Binding request, which also tells us the server's other address
+    let response = match binding(&socket, server, None, &Timers::default()) {
+        Some(response) => response,
+        None => {
+            eprintln!("no response from {}", server);
+            process::exit(1);
+        },
+    };
+    let other = match response.other {
+        Some(other) => other,
+        None => {
+            eprintln!("{} does not support nat behaviour discovery", server);
+            process::exit(1);
+        },
+    };
+    let mapped = response.mapped;
+
+    println!("mapped address: {}", mapped);
+    println!("behind nat: {}", match local_address(&socket, &server) {
+        Some(local) if local == mapped => "no",
+        Some(_) => "yes",
+        None => "unknown",
+    });
+    println!("mapping: {}", mapping(&socket, &server, &mapped, &other));
+    println!("filtering: {}", filtering(&socket, &server, &other));
+    println!("hairpinning: {}", match hairpinning(&socket, &mapped) {
+        true => "supported",
+        false => "not supported",
+    });
+}
+
+// RFC 5780 section 4.3
+fn mapping(socket:&UdpSocket, server:&SocketAddr, mapped:&SocketAddr, other:&SocketAddr) -> &'static str {
+    // Test II: the alternate IP address with the primary port
+    let second = match binding(socket, SocketAddr::new(other.ip(), server.port()), None, &TEST_TIMERS) {
+        Some(response) => response.mapped,
+        None => return "unknown, no response from the alternate address",
+    };
+    if second == *mapped {
+        return "endpoint-independent";
+    }
+
+    // Test III: the alternate IP address and port
+    match binding(socket, *other, None, &TEST_TIMERS) {
+        Some(response) if response.mapped == second => "address-dependent",
+        Some(_) => "address and port-dependent",
+        None => "unknown, no response from the alternate address",
+    }
+}
+
+// RFC 5780 section 4.4
+fn filtering(socket:&UdpSocket, server:&SocketAddr, other:&SocketAddr) -> &'static str {
+    // Test II: a response from the alternate IP address and port
+    if let Some(response) = binding(socket, *server, Some(ChangeRequest::with_flags(true, true)), &TEST_TIMERS) {
+        if response.origin == *other {
+            return "endpoint-independent";
+        }
+    }
+
+    // Test III: a response from the primary IP address but the alternate port
+    match binding(socket, *server, Some(ChangeRequest::with_flags(false, true)), &TEST_TIMERS) {
+        Some(response) if response.origin == SocketAddr::new(server.ip(), other.port()) => "address-dependent",
+        _ => "address and port-dependent",
+    }
+}
+
+// RFC 5780 section 4.5: a message sent to our own mapped address from another socket
+// has to be looped back by the NAT
+fn hairpinning(socket:&UdpSocket, mapped:&SocketAddr) -> bool {
+    let sender = match UdpSocket::bind(SocketAddr::new(unspecified(mapped), 0)) {
+        Ok(sender) => sender,
+        Err(_) => return false,
+    };
+    let id = rand::random::<[u8; 12]>();
+    let request = client::binding_request(&id, vec![]);
+
+    let mut timeout = TEST_TIMERS.rto;
+    for _ in 0..TEST_TIMERS.rc {
+        if sender.send_to(&request, mapped).is_err() {
+            return false;
+        }
+        if let Ok(Some(_)) = client::receive_udp(socket, &id, timeout) {
+            return true;
+        }
+        timeout *= 2;
+    }
+    false
+}
+
+fn binding(socket:&UdpSocket, destination:SocketAddr, change_request:Option<ChangeRequest>, timers:&Timers) -> Option<BindingResponse> {
+    let body = match change_request {
+        Some(change_request) => vec![AttributeBody::ChangeRequest(change_request)],
+        None => vec![],
+    };
+    let request = client::binding_request(&rand::random::<[u8; 12]>(), body);
+
+    match client::send_udp(socket, destination, &request, timers) {
+        Ok((message, origin)) => parse_response(&message, origin),
+        Err(_) => None,
+    }
+}
+
+fn parse_response(message:&[u8], origin:SocketAddr) -> Option<BindingResponse> {
+    let message = Message::decode(message).ok()?;
+    match message.header.mclass {
+        MessageClass::Success => (),
+        _ => return None,
+    }
+
+    let mapped = match message.get(MessageAttribute::XorMappedAddress) {
+        Ok(Some(AttributeBody::XorMappedAddress(address))) => address.address()?,
+        _ => return None,
+    };
+    let other = match message.get(MessageAttribute::OtherAddress) {
+        Ok(Some(AttributeBody::OtherAddress(address))) => address.address(),
+        _ => None,
+    };
+
+    Some(BindingResponse {
+        mapped,
+        other,
+        origin,
+    })
+}
+
+// The address the socket sends to the server from, as the server would see it without a NAT
+fn local_address(socket:&UdpSocket, server:&SocketAddr) -> Option<SocketAddr> {
+    let local = socket.local_addr().ok()?;
+    if !local.ip().is_unspecified() {
+        return Some(local);
+    }
+
+    // Connecting a throwaway socket picks the interface the server is reached through
+    let probe = UdpSocket::bind(SocketAddr::new(unspecified(server), 0)).ok()?;
+    probe.connect(server).ok()?;
+    Some(SocketAddr::new(probe.local_addr().ok()?.ip(), local.port()))
+}
+
+fn unspecified(address:&SocketAddr) -> IpAddr {
+    match address {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    }
+}
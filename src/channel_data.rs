@@ -0,0 +1,37 @@
+// ChannelData messages (RFC 5766 section 11.4) are not STUN messages: they carry
+// a channel number in place of the message type and have no transaction ID
+
+// Channel numbers clients may bind
+pub const MIN_CHANNEL: u16 = 0x4000;
+pub const MAX_CHANNEL: u16 = 0x7FFF;
+
+// The first two bits are 01, where STUN messages have 00
+pub fn is_channel_data(message:&[u8]) -> bool {
+    !message.is_empty() && message[0] >> 6 == 1
+}
+
+// Returns the channel number and application data
+pub fn parse(message:&[u8]) -> Option<(u16, &[u8])> {
+    if message.len() < 4 {
+        return None;
+    }
+
+    let channel = u16::from_be_bytes([message[0], message[1]]);
+    let length = u16::from_be_bytes([message[2], message[3]]) as usize;
+    if message.len() < 4 + length {
+        return None;
+    }
+
+    Some((channel, &message[4..(4 + length)]))
+}
+
+// Over stream transports the message is padded to a multiple of four bytes
+pub fn serialise(channel:u16, data:&[u8], pad:bool) -> Vec<u8> {
+    let mut message = channel.to_be_bytes().to_vec();
+    message.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    message.extend_from_slice(data);
+    if pad {
+        message.resize(message.len().div_ceil(4) * 4, 0);
+    }
+    message
+}
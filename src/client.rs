@@ -0,0 +1,245 @@
+extern crate rand;
+
+use std::error::Error;
+use std::fmt;
+use std::io::{ self, ErrorKind, Write };
+use std::net::{ SocketAddr, TcpStream, UdpSocket };
+use std::time::{ Duration, Instant };
+
+use crate::attributes::{ MessageAttribute, AttributeBody };
+use crate::attributes::xor_mapped_address::XorMappedAddress;
+use crate::framing;
+use crate::header::{ MessageClass, MessageType };
+use crate::message::Message;
+
+// Transactions over TCP are given up on as a whole after Ti (RFC 5389 section 7.2.2)
+const TCP_TIMEOUT: Duration = Duration::from_millis(39500);
+
+#[derive(Debug)]
+pub enum ClientError {
+    Io(io::Error),
+    Timeout,
+    // The server answered with an error response, carrying this error code
+    ErrorResponse(u16),
+    // The response could not be decoded, or lacked XOR-MAPPED-ADDRESS
+    InvalidResponse,
+}
+impl fmt::Display for ClientError {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientError::Io(e) => write!(f, "{}", e),
+            ClientError::Timeout => write!(f, "timed out"),
+            ClientError::ErrorResponse(code) => write!(f, "error response {}", code),
+            ClientError::InvalidResponse => write!(f, "invalid response"),
+        }
+    }
+}
+impl Error for ClientError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ClientError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// Retransmission timers for requests over UDP (RFC 5389 section 7.2.1)
+pub struct Timers {
+    // Initial retransmission timeout, doubled after every request
+    pub rto: Duration,
+    // Total number of requests sent
+    pub rc: u32,
+    // The last request is waited on for rm times the initial RTO
+    pub rm: u32,
+}
+impl Default for Timers {
+    fn default() -> Timers {
+        Timers {
+            rto: Duration::from_millis(500),
+            rc: 7,
+            rm: 16,
+        }
+    }
+}
+
+// Sends a Binding request over UDP and returns the reflexive address the server saw
+pub fn binding_udp(socket:&UdpSocket, server:SocketAddr) -> Result<XorMappedAddress, ClientError> {
+    let request = binding_request(&rand::random::<[u8; 12]>(), vec![]);
+    let (response, _) = send_udp(socket, server, &request, &Timers::default())?;
+    mapped_address(&response)
+}
+
+// Sends a Binding request over a new TCP connection and returns the reflexive address the server saw
+pub fn binding_tcp(server:SocketAddr) -> Result<XorMappedAddress, ClientError> {
+    let mut stream = match TcpStream::connect_timeout(&server, TCP_TIMEOUT) {
+        Ok(stream) => stream,
+        Err(ref e) if e.kind() == ErrorKind::TimedOut => return Err(ClientError::Timeout),
+        Err(e) => return Err(ClientError::Io(e)),
+    };
+    let request = binding_request(&rand::random::<[u8; 12]>(), vec![]);
+    let response = send_tcp(&mut stream, &request)?;
+    mapped_address(&response)
+}
+
+pub fn binding_request(id:&[u8; 12], attributes:Vec<AttributeBody>) -> Vec<u8> {
+    Message::builder(MessageType::Binding, MessageClass::Request).transaction_id(id)
+                                                                 .attributes(attributes)
+                                                                 .build()
+}
+
+// Sends a request, retransmitting it on the given schedule, and returns the first message
+// with the same transaction ID together with the address it came from
+pub fn send_udp(socket:&UdpSocket, server:SocketAddr, request:&[u8], timers:&Timers) -> Result<(Vec<u8>, SocketAddr), ClientError> {
+    let id = transaction_id(request)?;
+
+    let mut rto = timers.rto;
+    for attempt in 1..=timers.rc {
+        if let Err(e) = socket.send_to(request, server) {
+            return Err(ClientError::Io(e));
+        }
+        let wait = match attempt == timers.rc {
+            true => timers.rto * timers.rm,
+            false => rto,
+        };
+        if let Some(response) = receive_udp(socket, &id, wait)? {
+            return Ok(response);
+        }
+        rto *= 2;
+    }
+
+    Err(ClientError::Timeout)
+}
+
+// Waits for a message with the given transaction ID, discarding anything else, such as
+// responses to earlier transactions
+pub fn receive_udp(socket:&UdpSocket, id:&[u8; 12], timeout:Duration) -> Result<Option<(Vec<u8>, SocketAddr)>, ClientError> {
+    let deadline = Instant::now() + timeout;
+    let mut buf = vec![0; 65536];
+    loop {
+        let remaining = match deadline.checked_duration_since(Instant::now()) {
+            Some(remaining) if !remaining.is_zero() => remaining,
+            _ => return Ok(None),
+        };
+        if let Err(e) = socket.set_read_timeout(Some(remaining)) {
+            return Err(ClientError::Io(e));
+        }
+
+        let (amt, origin) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => return Ok(None),
+            // ICMP errors from earlier datagrams surface here and are not fatal
+            Err(ref e) if e.kind() == ErrorKind::ConnectionRefused => continue,
+            Err(e) => return Err(ClientError::Io(e)),
+        };
+        if amt >= 20 && &buf[8..20] == id {
+            return Ok(Some((buf[0..amt].to_vec(), origin)));
+        }
+    }
+}
+
+// Sends a request on a stream transport, where the transport itself handles retransmission,
+// and returns the message with the same transaction ID
+pub fn send_tcp(stream:&mut TcpStream, request:&[u8]) -> Result<Vec<u8>, ClientError> {
+    let id = transaction_id(request)?;
+    let deadline = Instant::now() + TCP_TIMEOUT;
+
+    if let Err(e) = stream.write_all(request) {
+        return Err(ClientError::Io(e));
+    }
+    loop {
+        let remaining = match deadline.checked_duration_since(Instant::now()) {
+            Some(remaining) if !remaining.is_zero() => remaining,
+            _ => return Err(ClientError::Timeout),
+        };
+        if let Err(e) = stream.set_read_timeout(Some(remaining)) {
+            return Err(ClientError::Io(e));
+        }
+
+        let message = match framing::read_message(stream) {
+            Ok(message) => message,
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => return Err(ClientError::Timeout),
+            Err(e) => return Err(ClientError::Io(e)),
+        };
+        if message.len() >= 20 && message[8..20] == id {
+            return Ok(message);
+        }
+    }
+}
+
+// Decodes XOR-MAPPED-ADDRESS from a Binding success response
+pub fn mapped_address(response:&[u8]) -> Result<XorMappedAddress, ClientError> {
+    let response = match Message::decode(response) {
+        Ok(response) => response,
+        Err(_) => return Err(ClientError::InvalidResponse),
+    };
+    match response.header.mclass {
+        MessageClass::Success => (),
+        MessageClass::Error => {
+            return match response.get(MessageAttribute::ErrorCode) {
+                Ok(Some(AttributeBody::ErrorCode(error_code))) => match error_code.code() {
+                    Some(code) => Err(ClientError::ErrorResponse(code)),
+                    None => Err(ClientError::InvalidResponse),
+                },
+                _ => Err(ClientError::InvalidResponse),
+            }
+        },
+        _ => return Err(ClientError::InvalidResponse),
+    }
+
+    match response.get(MessageAttribute::XorMappedAddress) {
+        Ok(Some(AttributeBody::XorMappedAddress(address))) => Ok(address),
+        _ => Err(ClientError::InvalidResponse),
+    }
+}
+
+fn transaction_id(request:&[u8]) -> Result<[u8; 12], ClientError> {
+    if request.len() < 20 {
+        return Err(ClientError::Io(io::Error::new(ErrorKind::InvalidInput, "request shorter than a STUN header")));
+    }
+    let mut id = [0; 12];
+    id.copy_from_slice(&request[8..20]);
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::attributes::error_code::{ ErrorCode, ErrorCodeType };
+
+    #[test]
+    fn error_responses_carry_their_code() {
+        let response = Message::builder(MessageType::Binding, MessageClass::Error)
+            .transaction_id(&[0; 12])
+            .attribute(AttributeBody::ErrorCode(ErrorCode::with_type(ErrorCodeType::Unauthorised)))
+            .build();
+        assert!(matches!(mapped_address(&response), Err(ClientError::ErrorResponse(401))));
+    }
+
+    #[test]
+    fn udp_requests_are_retransmitted_on_schedule() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let timers = Timers {
+            rto: Duration::from_millis(20),
+            rc: 4,
+            rm: 5,
+        };
+
+        // Sent at 0, 20, 60 and 140ms, then given up on 100ms after the last
+        let start = Instant::now();
+        let request = binding_request(&[1; 12], vec![]);
+        assert!(matches!(send_udp(&socket, server.local_addr().unwrap(), &request, &timers), Err(ClientError::Timeout)));
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(240) && elapsed < Duration::from_millis(1000), "{:?}", elapsed);
+
+        server.set_nonblocking(true).unwrap();
+        let mut buf = [0; 64];
+        let mut sent = 0;
+        while let Ok((amt, _)) = server.recv_from(&mut buf) {
+            assert_eq!(&buf[0..amt], &request[..]);
+            sent += 1;
+        }
+        assert_eq!(sent, 4);
+    }
+}
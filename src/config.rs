@@ -1,47 +1,447 @@
 extern crate serde;
 extern crate toml;
 
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::Read;
-use std::path::Path;
+use std::io::{ ErrorKind, Read };
+use std::net::{ IpAddr, SocketAddr };
+use std::path::{ Path, PathBuf };
 
 use serde::{ Deserialize, Serialize };
 
+use crate::auth::Mechanism;
+
 pub struct Config {
-    pub port: String,
+    pub listeners: Vec<Listener>,
+    pub credentials: Mechanism,
+    pub realm: String,
+    pub users: HashMap<String, String>,
+    // Secrets TURN REST API credentials may be signed with
+    pub shared_secrets: Vec<String>,
+    pub relay_ip: IpAddr,
+    // Peers that relayed traffic may not be sent to
+    pub denied_peers: Vec<IpRange>,
+    // Most allocations one user, and everyone together, may hold at once; unlimited if unset
+    pub user_quota: Option<usize>,
+    pub total_quota: Option<usize>,
+    // Set when NAT behaviour discovery is on, which answers on the first listener's UDP port
+    pub alternate: Option<SocketAddr>,
+    // PEM files for TLS and DTLS, set whenever a listener has a tls_port or dtls_port
+    pub tls_certificate: Option<PathBuf>,
+    pub tls_private_key: Option<PathBuf>,
 }
 
-#[derive(Deserialize, Serialize)]
+// An address the server listens on, with a port for each transport served there
+pub struct Listener {
+    pub ip: IpAddr,
+    pub udp_port: Option<u16>,
+    pub tcp_port: Option<u16>,
+    pub tls_port: Option<u16>,
+    pub dtls_port: Option<u16>,
+    // Whether a listener on an IPv6 address refuses IPv4 clients, rather than serving both
+    pub v6_only: bool,
+    // Most TCP, TLS and DTLS connections served at once, between them
+    pub tcp_workers: usize,
+    // Seconds a TCP connection may go without a message while it has no allocation
+    pub tcp_idle_timeout: u64,
+    // Seconds a TCP connection may stall partway through a message
+    pub tcp_read_timeout: u64,
+}
+
+// Loopback, private and link-local addresses, which would let TURN clients reach the server
+// itself or the network it sits on
+const DEFAULT_DENIED_PEERS: [&str; 10] = [
+    "0.0.0.0/8", "10.0.0.0/8", "127.0.0.0/8", "169.254.0.0/16", "172.16.0.0/12", "192.168.0.0/16",
+    "::/128", "::1/128", "fc00::/7", "fe80::/10",
+];
+
+// An address prefix such as 10.0.0.0/8
+#[derive(Copy, Clone)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix: u8,
+}
+impl IpRange {
+    fn parse(range: &str) -> Option<IpRange> {
+        let (network, prefix) = range.split_once('/')?;
+        let network = network.parse::<IpAddr>().ok()?;
+        let prefix = prefix.parse::<u8>().ok()?;
+        let bits = match network {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        match prefix <= bits {
+            true => Some(IpRange {
+                network,
+                prefix,
+            }),
+            false => None,
+        }
+    }
+
+    // IPv4-mapped IPv6 addresses are matched as the IPv4 addresses they are
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        let ip = match ip {
+            IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+                Some(ip) => IpAddr::V4(ip),
+                None => IpAddr::V6(*ip),
+            },
+            ip => *ip,
+        };
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            },
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            },
+            _ => false,
+        }
+    }
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
 struct LoadableConfig {
+    ip: Option<String>,
     port: Option<String>,
+    listen: Option<Vec<LoadableListener>>,
+    credentials: Option<String>,
+    realm: Option<String>,
+    users: Option<HashMap<String, String>>,
+    shared_secrets: Option<Vec<String>>,
+    relay_ip: Option<String>,
+    denied_peers: Option<Vec<String>>,
+    user_quota: Option<usize>,
+    total_quota: Option<usize>,
+    alternate_ip: Option<String>,
+    alternate_port: Option<String>,
+    tcp_workers: Option<usize>,
+    tcp_idle_timeout: Option<u64>,
+    tcp_read_timeout: Option<u64>,
+    tls_certificate: Option<String>,
+    tls_private_key: Option<String>,
 }
 
-pub fn get_config(filename: &str) -> Config {
-    let default = Config {
-        port: String::from("3478"),
-    };
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct LoadableListener {
+    ip: String,
+    udp_port: Option<u16>,
+    tcp_port: Option<u16>,
+    tls_port: Option<u16>,
+    dtls_port: Option<u16>,
+    v6_only: Option<bool>,
+    tcp_workers: Option<usize>,
+    tcp_idle_timeout: Option<u64>,
+    tcp_read_timeout: Option<u64>,
+}
 
+// A missing file gives the defaults, but anything wrong with a file that is there is
+// reported rather than papered over
+pub fn get_config(filename: &str) -> Result<Config, String> {
     let mut config_file = match File::open(Path::new(filename)) {
         Ok(config_file) => config_file,
-        Err(_) => return default,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return validate(LoadableConfig::default()),
+        Err(e) => return Err(format!("unable to open {}: {}", filename, e)),
     };
     let mut config_string = String::new();
-    match config_file.read_to_string(&mut config_string) {
-        Ok(_) => (),
-        Err(_) => return default,
+    if let Err(e) = config_file.read_to_string(&mut config_string) {
+        return Err(format!("unable to read {}: {}", filename, e));
+    }
+
+    match toml::from_str(&config_string) {
+        Ok(loaded_config) => validate(loaded_config),
+        Err(e) => Err(format!("{}: {}", filename, e)),
     }
+}
 
-    let loaded_config:LoadableConfig = match toml::from_str(&config_string) {
-        Ok(loaded_config) => loaded_config,
-        Err(_) => return default,
+fn validate(loaded_config: LoadableConfig) -> Result<Config, String> {
+    let tcp_workers = loaded_config.tcp_workers.unwrap_or(256);
+    let tcp_idle_timeout = loaded_config.tcp_idle_timeout.unwrap_or(60);
+    let tcp_read_timeout = loaded_config.tcp_read_timeout.unwrap_or(10);
+
+    // ip and port are the single UDP and TCP listener from before [[listen]] existed, which
+    // also serves TLS and DTLS on 5349 once there is a certificate
+    let loadable_listeners = match (loaded_config.listen, &loaded_config.ip, &loaded_config.port) {
+        (Some(_), Some(_), _) | (Some(_), _, Some(_)) => return Err(String::from("ip and port cannot be used together with [[listen]]")),
+        (Some(listen), None, None) => listen,
+        (None, ip, port) => {
+            let port = match port {
+                Some(port) => match port.parse::<u16>() {
+                    Ok(port) => port,
+                    Err(_) => return Err(format!("invalid port {}", port)),
+                },
+                None => 3478,
+            };
+            vec![LoadableListener {
+                ip: ip.clone().unwrap_or_else(|| String::from("0.0.0.0")),
+                udp_port: Some(port),
+                tcp_port: Some(port),
+                tls_port: loaded_config.tls_certificate.as_ref().map(|_| 5349),
+                dtls_port: loaded_config.tls_certificate.as_ref().map(|_| 5349),
+                v6_only: None,
+                tcp_workers: None,
+                tcp_idle_timeout: None,
+                tcp_read_timeout: None,
+            }]
+        },
+    };
+    if loadable_listeners.is_empty() {
+        return Err(String::from("[[listen]] needs at least one entry"));
+    }
+
+    let mut listeners = vec![];
+    let mut bound: Vec<(&str, SocketAddr)> = vec![];
+    for listener in loadable_listeners {
+        let ip = match listener.ip.parse::<IpAddr>() {
+            Ok(ip) => ip,
+            Err(_) => return Err(format!("invalid listener ip {}", listener.ip)),
+        };
+        if listener.udp_port.is_none() && listener.tcp_port.is_none() && listener.tls_port.is_none() && listener.dtls_port.is_none() {
+            return Err(format!("listener {} has no udp_port, tcp_port, tls_port or dtls_port", ip));
+        }
+        for (transport, port) in [("udp", listener.udp_port), ("tcp", listener.tcp_port), ("tls", listener.tls_port), ("dtls", listener.dtls_port)] {
+            let port = match port {
+                Some(port) => port,
+                None => continue,
+            };
+            if port == 0 {
+                return Err(format!("listener {} has {} port 0", ip, transport));
+            }
+            // Transports over the same protocol cannot share a port: TCP with TLS, UDP with DTLS
+            let address = SocketAddr::new(ip, port);
+            let datagram = |transport: &str| transport == "udp" || transport == "dtls";
+            if bound.iter().any(|(other, other_address)| *other_address == address && datagram(other) == datagram(transport)) {
+                return Err(format!("{} listener at {} clashes with another listener", transport, address));
+            }
+            bound.push((transport, address));
+        }
+        if (listener.tls_port.is_some() || listener.dtls_port.is_some()) && (loaded_config.tls_certificate.is_none() || loaded_config.tls_private_key.is_none()) {
+            return Err(format!("listener {} has a tls_port or dtls_port, which needs tls_certificate and tls_private_key", ip));
+        }
+        if listener.v6_only.is_some() && ip.is_ipv4() {
+            return Err(format!("v6_only is only meaningful for IPv6 listeners, not {}", ip));
+        }
+
+        let tcp_workers = listener.tcp_workers.unwrap_or(tcp_workers);
+        if tcp_workers == 0 {
+            return Err(format!("listener {} has tcp_workers = 0", ip));
+        }
+        // A timeout of 0 would drop every connection before its first message arrived
+        let tcp_idle_timeout = listener.tcp_idle_timeout.unwrap_or(tcp_idle_timeout);
+        if tcp_idle_timeout == 0 {
+            return Err(format!("listener {} has tcp_idle_timeout = 0", ip));
+        }
+        let tcp_read_timeout = listener.tcp_read_timeout.unwrap_or(tcp_read_timeout);
+        if tcp_read_timeout == 0 {
+            return Err(format!("listener {} has tcp_read_timeout = 0", ip));
+        }
+        listeners.push(Listener {
+            ip,
+            udp_port: listener.udp_port,
+            tcp_port: listener.tcp_port,
+            tls_port: listener.tls_port,
+            dtls_port: listener.dtls_port,
+            v6_only: listener.v6_only.unwrap_or(false),
+            tcp_workers,
+            tcp_idle_timeout,
+            tcp_read_timeout,
+        });
+    }
+
+    let credentials = match loaded_config.credentials.as_deref() {
+        Some("long-term") | None => Mechanism::LongTerm,
+        Some("short-term") => Mechanism::ShortTerm,
+        Some(credentials) => return Err(format!("credentials must be \"long-term\" or \"short-term\", not \"{}\"", credentials)),
     };
 
-    let config = Config {
-        port: match &loaded_config.port {
-            Some(port) => port.clone(),
-            None => String::from("3478"),
+    let shared_secrets = loaded_config.shared_secrets.unwrap_or_default();
+    if shared_secrets.iter().any(|secret| secret.is_empty()) {
+        return Err(String::from("shared_secrets cannot contain an empty secret"));
+    }
+
+    let relay_ip = match loaded_config.relay_ip {
+        Some(relay_ip) => match relay_ip.parse::<IpAddr>() {
+            Ok(relay_ip) => relay_ip,
+            Err(_) => return Err(format!("invalid relay_ip {}", relay_ip)),
         },
+        None => IpAddr::from([127, 0, 0, 1]),
+    };
+
+    let denied_peers = match loaded_config.denied_peers {
+        Some(denied_peers) => denied_peers,
+        None => DEFAULT_DENIED_PEERS.iter().map(|range| String::from(*range)).collect(),
     };
+    let mut denied_ranges = vec![];
+    for range in denied_peers {
+        match IpRange::parse(&range) {
+            Some(range) => denied_ranges.push(range),
+            None => return Err(format!("invalid denied_peers range {}", range)),
+        }
+    }
+
+    // A quota of 0 would refuse every allocation
+    if loaded_config.user_quota == Some(0) || loaded_config.total_quota == Some(0) {
+        return Err(String::from("user_quota and total_quota must be at least 1"));
+    }
 
-    config
+    // NAT behaviour discovery answers from every combination of two addresses and ports
+    let alternate = match loaded_config.alternate_ip {
+        Some(alternate_ip) => {
+            let alternate_ip = match alternate_ip.parse::<IpAddr>() {
+                Ok(alternate_ip) => alternate_ip,
+                Err(_) => return Err(format!("invalid alternate_ip {}", alternate_ip)),
+            };
+            let alternate_port = match loaded_config.alternate_port.as_deref().unwrap_or("3479").parse::<u16>() {
+                Ok(alternate_port) if alternate_port != 0 => alternate_port,
+                _ => return Err(String::from("invalid alternate_port")),
+            };
+            let primary = match (listeners[0].ip, listeners[0].udp_port) {
+                (ip, Some(port)) if !ip.is_unspecified() => SocketAddr::new(ip, port),
+                _ => return Err(String::from("nat discovery needs the first listener to have a udp_port and a concrete ip")),
+            };
+            if primary.ip() == alternate_ip || primary.port() == alternate_port || primary.is_ipv4() != alternate_ip.is_ipv4() {
+                return Err(String::from("nat discovery needs an alternate_ip and alternate_port distinct from the first listener's, of the same family"));
+            }
+            Some(SocketAddr::new(alternate_ip, alternate_port))
+        },
+        None => None,
+    };
+
+    Ok(Config {
+        listeners,
+        credentials,
+        realm: loaded_config.realm.unwrap_or_else(|| String::from("localhost")),
+        users: loaded_config.users.unwrap_or_default(),
+        shared_secrets,
+        relay_ip,
+        denied_peers: denied_ranges,
+        user_quota: loaded_config.user_quota,
+        total_quota: loaded_config.total_quota,
+        alternate,
+        tls_certificate: loaded_config.tls_certificate.map(PathBuf::from),
+        tls_private_key: loaded_config.tls_private_key.map(PathBuf::from),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(config:&str) -> Result<Config, String> {
+        validate(toml::from_str(config).unwrap())
+    }
+
+    fn error(config:&str) -> String {
+        match load(config) {
+            Ok(_) => panic!("accepted:\n{}", config),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn ip_ranges_match_by_prefix() {
+        let private = IpRange::parse("172.16.0.0/12").unwrap();
+        assert!(private.contains(&"172.31.255.1".parse().unwrap()));
+        assert!(!private.contains(&"172.32.0.1".parse().unwrap()));
+        assert!(private.contains(&"::ffff:172.16.0.1".parse().unwrap()));
+        assert!(!private.contains(&"fe80::1".parse().unwrap()));
+
+        let link_local = IpRange::parse("fe80::/10").unwrap();
+        assert!(link_local.contains(&"fe80::1".parse().unwrap()));
+        assert!(!link_local.contains(&"2001:db8::1".parse().unwrap()));
+
+        assert!(IpRange::parse("0.0.0.0/0").unwrap().contains(&"203.0.113.1".parse().unwrap()));
+        assert!(IpRange::parse("10.0.0.0/33").is_none());
+        assert!(IpRange::parse("10.0.0.0").is_none());
+    }
+
+    #[test]
+    fn loopback_peers_are_denied_by_default() {
+        let config = validate(LoadableConfig::default()).unwrap();
+        for peer in ["127.0.0.1", "10.1.2.3", "192.168.1.1", "169.254.0.1", "::1", "fd00::1"] {
+            let peer = peer.parse().unwrap();
+            assert!(config.denied_peers.iter().any(|range| range.contains(&peer)), "{} not denied", peer);
+        }
+        assert!(!config.denied_peers.iter().any(|range| range.contains(&"203.0.113.1".parse().unwrap())));
+    }
+
+    #[test]
+    fn ip_and_port_are_a_single_listener() {
+        let config = load("ip = \"127.0.0.1\"\nport = \"3500\"\n").unwrap();
+        assert_eq!(config.listeners.len(), 1);
+        let listener = &config.listeners[0];
+        assert_eq!(listener.ip, "127.0.0.1".parse::<IpAddr>().unwrap());
+        assert_eq!((listener.udp_port, listener.tcp_port, listener.tls_port, listener.dtls_port), (Some(3500), Some(3500), None, None));
+
+        // TLS and DTLS come on 5349 once there is a certificate
+        let config = load("tls_certificate = \"cert.pem\"\ntls_private_key = \"key.pem\"\n").unwrap();
+        let listener = &config.listeners[0];
+        assert_eq!(listener.ip, "0.0.0.0".parse::<IpAddr>().unwrap());
+        assert_eq!((listener.udp_port, listener.tcp_port, listener.tls_port, listener.dtls_port), (Some(3478), Some(3478), Some(5349), Some(5349)));
+
+        assert_eq!(error("port = \"stun\"\n"), "invalid port stun");
+    }
+
+    #[test]
+    fn ip_and_port_cannot_be_mixed_with_listen() {
+        for config in ["ip = \"127.0.0.1\"\n", "port = \"3500\"\n"] {
+            let config = format!("{}[[listen]]\nip = \"127.0.0.1\"\nudp_port = 3478\n", config);
+            assert_eq!(error(&config), "ip and port cannot be used together with [[listen]]");
+        }
+    }
+
+    #[test]
+    fn transports_over_the_same_protocol_cannot_share_a_port() {
+        let certificate = "tls_certificate = \"cert.pem\"\ntls_private_key = \"key.pem\"\n";
+        assert_eq!(error(&format!("{}[[listen]]\nip = \"127.0.0.1\"\nudp_port = 3478\ndtls_port = 3478\n", certificate)),
+                   "dtls listener at 127.0.0.1:3478 clashes with another listener");
+        assert_eq!(error(&format!("{}[[listen]]\nip = \"127.0.0.1\"\ntcp_port = 3478\ntls_port = 3478\n", certificate)),
+                   "tls listener at 127.0.0.1:3478 clashes with another listener");
+        assert_eq!(error("[[listen]]\nip = \"127.0.0.1\"\nudp_port = 3478\n[[listen]]\nip = \"127.0.0.1\"\nudp_port = 3478\n"),
+                   "udp listener at 127.0.0.1:3478 clashes with another listener");
+
+        // UDP and TCP are different protocols, as are DTLS and TLS
+        assert!(load(&format!("{}[[listen]]\nip = \"127.0.0.1\"\nudp_port = 3478\ntcp_port = 3478\ntls_port = 5349\ndtls_port = 5349\n", certificate)).is_ok());
+    }
+
+    #[test]
+    fn v6_only_is_for_ipv6_listeners() {
+        assert_eq!(error("[[listen]]\nip = \"127.0.0.1\"\nudp_port = 3478\nv6_only = true\n"),
+                   "v6_only is only meaningful for IPv6 listeners, not 127.0.0.1");
+        let config = load("[[listen]]\nip = \"::\"\nudp_port = 3478\nv6_only = true\n").unwrap();
+        assert!(config.listeners[0].v6_only);
+    }
+
+    #[test]
+    fn tls_needs_a_certificate_and_key() {
+        let expected = "listener 127.0.0.1 has a tls_port or dtls_port, which needs tls_certificate and tls_private_key";
+        assert_eq!(error("[[listen]]\nip = \"127.0.0.1\"\ntls_port = 5349\n"), expected);
+        assert_eq!(error("tls_certificate = \"cert.pem\"\n[[listen]]\nip = \"127.0.0.1\"\ndtls_port = 5349\n"), expected);
+        assert_eq!(error("tls_private_key = \"key.pem\"\n[[listen]]\nip = \"127.0.0.1\"\ntls_port = 5349\n"), expected);
+    }
+
+    #[test]
+    fn workers_and_timeouts_cannot_be_zero() {
+        for setting in ["tcp_workers", "tcp_idle_timeout", "tcp_read_timeout"] {
+            let expected = format!("listener 127.0.0.1 has {} = 0", setting);
+            assert_eq!(error(&format!("{} = 0\n[[listen]]\nip = \"127.0.0.1\"\ntcp_port = 3478\n", setting)), expected);
+            assert_eq!(error(&format!("[[listen]]\nip = \"127.0.0.1\"\ntcp_port = 3478\n{} = 0\n", setting)), expected);
+
+            // A listener's own setting stands in for the global one
+            let config = load(&format!("{} = 0\n[[listen]]\nip = \"127.0.0.1\"\ntcp_port = 3478\n{} = 5\n", setting, setting));
+            assert!(config.is_ok());
+        }
+    }
+
+    #[test]
+    fn quotas_cannot_be_zero() {
+        assert_eq!(error("user_quota = 0\n"), "user_quota and total_quota must be at least 1");
+        assert_eq!(error("total_quota = 0\n"), "user_quota and total_quota must be at least 1");
+        let config = load("user_quota = 2\n").unwrap();
+        assert_eq!((config.user_quota, config.total_quota), (Some(2), None));
+    }
 }
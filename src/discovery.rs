@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{ IpAddr, SocketAddr, UdpSocket };
+use std::sync::Arc;
+
+use crate::transport;
+
+// RFC 5780 NAT behaviour discovery needs the server to listen on two IP addresses
+// and two ports, so that it can answer from an address other than the one asked
+pub struct Discovery {
+    primary: SocketAddr,
+    alternate: SocketAddr,
+    sockets: HashMap<SocketAddr, Arc<UdpSocket>>,
+}
+impl Discovery {
+    pub fn bind(primary:SocketAddr, alternate:SocketAddr, v6_only:bool) -> io::Result<Discovery> {
+        let mut sockets = HashMap::new();
+        for ip in [primary.ip(), alternate.ip()] {
+            for port in [primary.port(), alternate.port()] {
+                let address = SocketAddr::new(ip, port);
+                sockets.insert(address, Arc::new(transport::bind_udp(address, v6_only)?));
+            }
+        }
+
+        Ok(Discovery {
+            primary,
+            alternate,
+            sockets,
+        })
+    }
+
+    pub fn sockets(&self) -> Vec<Arc<UdpSocket>> {
+        self.sockets.values().cloned().collect()
+    }
+
+    // Whether local is one of the addresses discovery answers on, rather than some other listener
+    pub fn serves(&self, local:&SocketAddr) -> bool {
+        self.sockets.contains_key(local)
+    }
+
+    // The address differing from local in both IP address and port, for OTHER-ADDRESS
+    pub fn other_address(&self, local:&SocketAddr) -> SocketAddr {
+        self.origin_address(local, true, true)
+    }
+
+    // The socket a response to a request received on local should be sent from
+    pub fn origin(&self, local:&SocketAddr, change_ip:bool, change_port:bool) -> Option<(Arc<UdpSocket>, SocketAddr)> {
+        let origin = self.origin_address(local, change_ip, change_port);
+        self.sockets.get(&origin).map(|socket| (Arc::clone(socket), origin))
+    }
+
+    fn origin_address(&self, local:&SocketAddr, change_ip:bool, change_port:bool) -> SocketAddr {
+        let ip = match change_ip {
+            true => self.other_ip(&local.ip()),
+            false => local.ip(),
+        };
+        let port = match change_port {
+            true => self.other_port(local.port()),
+            false => local.port(),
+        };
+        SocketAddr::new(ip, port)
+    }
+
+    fn other_ip(&self, ip:&IpAddr) -> IpAddr {
+        match *ip == self.primary.ip() {
+            true => self.alternate.ip(),
+            false => self.primary.ip(),
+        }
+    }
+
+    fn other_port(&self, port:u16) -> u16 {
+        match port == self.primary.port() {
+            true => self.alternate.port(),
+            false => self.primary.port(),
+        }
+    }
+}
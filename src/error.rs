@@ -0,0 +1,73 @@
+use std::error::Error;
+use std::fmt;
+
+// Why a message, header or attribute could not be decoded
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    // The input ends before the header, an attribute or a value does
+    Truncated,
+    // A value is not the length its attribute requires
+    BadLength,
+    // An address family other than IPv4 (0x01) or IPv6 (0x02)
+    BadFamily(u8),
+    // A text value is not valid UTF-8
+    InvalidUtf8,
+    // A text value is longer than its attribute allows
+    TooLong,
+    // The first two bits of the header are not zero
+    NotStun,
+    BadMagicCookie,
+    UnknownMethod(u16),
+    // An ERROR-CODE with a code that has no ErrorCodeType
+    UnknownErrorCode(u16),
+    // Anything else a value may not contain
+    InvalidValue,
+}
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Truncated => write!(f, "truncated"),
+            DecodeError::BadLength => write!(f, "bad attribute length"),
+            DecodeError::BadFamily(family) => write!(f, "bad address family {:#04x}", family),
+            DecodeError::InvalidUtf8 => write!(f, "invalid utf-8"),
+            DecodeError::TooLong => write!(f, "value too long"),
+            DecodeError::NotStun => write!(f, "not a stun message"),
+            DecodeError::BadMagicCookie => write!(f, "bad magic cookie"),
+            DecodeError::UnknownMethod(method) => write!(f, "unknown method {:#05x}", method),
+            DecodeError::UnknownErrorCode(code) => write!(f, "unknown error code {}", code),
+            DecodeError::InvalidValue => write!(f, "invalid value"),
+        }
+    }
+}
+impl Error for DecodeError {}
+
+// Why an attribute type could not be registered
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RegistryError {
+    // The type code belongs to an attribute this crate has a type for
+    Builtin(u16),
+    AlreadyRegistered(u16),
+}
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegistryError::Builtin(type_code) => write!(f, "attribute type {:#06x} is built in", type_code),
+            RegistryError::AlreadyRegistered(type_code) => write!(f, "attribute type {:#06x} is already registered", type_code),
+        }
+    }
+}
+impl Error for RegistryError {}
+
+// Why an XOR-encoded address attribute could not be made: the key it is XORed with has to
+// be as long as the address, 4 bytes for IPv4 and 16 for IPv6
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct AddressKeyError {
+    pub expected: usize,
+    pub actual: usize,
+}
+impl fmt::Display for AddressKeyError {
+    fn fmt(&self, f:&mut fmt::Formatter) -> fmt::Result {
+        write!(f, "address key is {} bytes, expected {}", self.actual, self.expected)
+    }
+}
+impl Error for AddressKeyError {}
@@ -0,0 +1,90 @@
+use std::io::{ self, ErrorKind, Read };
+use std::net::TcpStream;
+use std::time::{ Duration, Instant };
+
+use crate::channel_data;
+
+// A stream whose reads can be given up on after a while
+pub trait TimedRead: Read {
+    fn set_read_timeout(&mut self, timeout:Option<Duration>) -> io::Result<()>;
+}
+impl TimedRead for TcpStream {
+    fn set_read_timeout(&mut self, timeout:Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+// Reads the next STUN or ChannelData message off a stream transport. STUN
+// messages are framed by the header length field, ChannelData by its own
+// length field plus padding to a multiple of four bytes.
+pub fn read_message<R: Read>(reader:&mut R) -> io::Result<Vec<u8>> {
+    let mut message = vec![0; 4];
+    reader.read_exact(&mut message)?;
+
+    let length = u16::from_be_bytes([message[2], message[3]]) as usize;
+    let remaining = if channel_data::is_channel_data(&message) {
+        length.div_ceil(4) * 4
+    } else if message[0] >> 6 == 0 {
+        16 + length
+    } else {
+        // Framing is lost, so the stream cannot be read any further
+        return Err(io::Error::new(ErrorKind::InvalidData, "not a STUN or ChannelData message"));
+    };
+
+    message.resize(4 + remaining, 0);
+    reader.read_exact(&mut message[4..])?;
+
+    Ok(message)
+}
+
+// Like read_message, but returns None if no message starts within idle, and fails if
+// the rest of one takes longer than timeout to arrive
+pub fn read_message_timeout<R: TimedRead>(stream:&mut R, idle:Duration, timeout:Duration) -> io::Result<Option<Vec<u8>>> {
+    stream.set_read_timeout(Some(idle))?;
+    let mut first = [0; 1];
+    match stream.read(&mut first) {
+        Ok(0) => return Err(io::Error::new(ErrorKind::UnexpectedEof, "connection closed")),
+        Ok(_) => (),
+        Err(ref e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    let mut stream = Deadline {
+        stream,
+        deadline: Instant::now() + timeout,
+    };
+    read_message(&mut (&first[..]).chain(&mut stream)).map(Some)
+}
+
+// Gives every read what is left of a time limit, so a message trickled in a byte at a
+// time can't take longer than one sent whole
+struct Deadline<'a, R> {
+    stream: &'a mut R,
+    deadline: Instant,
+}
+impl<R: TimedRead> Read for Deadline<'_, R> {
+    fn read(&mut self, buf:&mut [u8]) -> io::Result<usize> {
+        let remaining = match self.deadline.checked_duration_since(Instant::now()) {
+            Some(remaining) if remaining > Duration::ZERO => remaining,
+            _ => return Err(io::Error::new(ErrorKind::TimedOut, "message timed out")),
+        };
+        self.stream.set_read_timeout(Some(remaining))?;
+        self.stream.read(buf)
+    }
+}
+
+// Reads the next message off a transport that keeps message boundaries, such as DTLS,
+// returning None if none arrives within idle
+pub fn read_datagram_timeout<R: TimedRead>(reader:&mut R, idle:Duration) -> io::Result<Option<Vec<u8>>> {
+    reader.set_read_timeout(Some(idle))?;
+    let mut message = vec![0; 65536];
+    match reader.read(&mut message) {
+        Ok(0) => Err(io::Error::new(ErrorKind::UnexpectedEof, "connection closed")),
+        Ok(amt) => {
+            message.truncate(amt);
+            Ok(Some(message))
+        },
+        Err(ref e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => Ok(None),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,60 @@
+use crate::attributes::{ Attributes, MessageAttribute, AttributeBody };
+use crate::attributes::error_code::ErrorCodeType;
+use crate::attributes::lifetime::Lifetime;
+use crate::attributes::requested_transport;
+use crate::attributes::xor_mapped_address::{ self, XorMappedAddress };
+use crate::attributes::xor_relayed_address::XorRelayedAddress;
+use crate::handlers::{ self, MessageHandler };
+use crate::header::MessageHeader;
+use crate::server::Server;
+use crate::transport::{ Connection, Transport };
+
+pub struct Allocate {
+}
+impl MessageHandler for Allocate {
+    fn indication(_header: &MessageHeader, _body:&Attributes, _connection: &Connection, _server: &Server) {
+    }
+    fn request(header: &MessageHeader, body:&Attributes, connection: &Connection, server: &Server) -> Result<Vec<AttributeBody>, ErrorCodeType> {
+        // Without credentials to check, anyone could use the server as an open relay
+        if !server.authenticator.enabled() {
+            return Err(ErrorCodeType::Forbidden);
+        }
+        let username = handlers::username(body);
+        let protocol = match body.get(MessageAttribute::RequestedTransport) {
+            Some(AttributeBody::RequestedTransport(transport)) => transport.protocol(),
+            _ => None,
+        };
+        let protocol = match protocol {
+            Some(requested_transport::UDP) => requested_transport::UDP,
+            // TCP relays are controlled over a TCP or TLS connection (RFC 6062 section 5.1)
+            Some(requested_transport::TCP) if matches!(connection.tuple.transport, Transport::Tcp | Transport::Tls) => requested_transport::TCP,
+            Some(requested_transport::TCP) => return Err(ErrorCodeType::BadRequest),
+            Some(_) => return Err(ErrorCodeType::UnsupportedTransport),
+            None => return Err(ErrorCodeType::BadRequest),
+        };
+        let lifetime = match body.get(MessageAttribute::Lifetime) {
+            Some(AttributeBody::Lifetime(lifetime)) => lifetime.value(),
+            _ => None,
+        };
+
+        let (relayed_address, lifetime) = server.allocations.allocate(connection, &header.id, protocol, lifetime, username)?;
+
+        let key = xor_mapped_address::address_key(&relayed_address, &header.id);
+        let xor_relayed_address = match XorRelayedAddress::with_address(relayed_address, key) {
+            Ok(xor_relayed_address) => xor_relayed_address,
+            Err(_) => return Err(ErrorCodeType::ServerError),
+        };
+        let mapped_address = connection.mapped_address();
+        let key = xor_mapped_address::address_key(&mapped_address, &header.id);
+        let xor_mapped_address = match XorMappedAddress::with_address(mapped_address, key) {
+            Ok(xor_mapped_address) => xor_mapped_address,
+            Err(_) => return Err(ErrorCodeType::ServerError),
+        };
+
+        Ok(vec![
+            AttributeBody::XorRelayedAddress(xor_relayed_address),
+            AttributeBody::Lifetime(Lifetime::with_value(lifetime)),
+            AttributeBody::XorMappedAddress(xor_mapped_address),
+        ])
+    }
+}
@@ -1,27 +1,82 @@
-use std::collections::HashMap;
-use std::net::{ IpAddr, SocketAddr };
+use std::net::SocketAddr;
 
-use crate::attributes::{ self, MessageAttribute, AttributeBody };
-use crate::attributes::xor_mapped_address::XorMappedAddress;
+use crate::attributes::{ Attributes, MessageAttribute, AttributeBody };
+use crate::attributes::error_code::ErrorCodeType;
+use crate::attributes::other_address::OtherAddress;
+use crate::attributes::padding::Padding;
+use crate::attributes::response_origin::ResponseOrigin;
+use crate::attributes::xor_mapped_address::{ self, XorMappedAddress };
+use crate::discovery::Discovery;
 use crate::handlers::MessageHandler;
 use crate::header::MessageHeader;
+use crate::server::Server;
+use crate::transport::{ Connection, Transport };
 
 pub struct Binding {
 }
 impl MessageHandler for Binding {
-    fn indication(_header: &MessageHeader, _body:&HashMap<MessageAttribute, AttributeBody>, _origin: &SocketAddr){
+    fn indication(_header: &MessageHeader, _body:&Attributes, _connection: &Connection, _server: &Server) {
     }
-    fn request(header: &MessageHeader, _body:&HashMap<MessageAttribute, AttributeBody>, origin: &SocketAddr) -> Result<Option<Vec<u8>>, ()> {
-        let origin = origin.clone();
-        let key = match origin.ip() {
-            IpAddr::V4(_) => vec![0x21, 0x12, 0xA4, 0x42],
-            IpAddr::V6(_) => vec![0x21, 0x12, 0xA4, 0x42].iter().chain(&header.id).map(|b| *b).collect::<Vec<u8>>(),
-        };
-        let xor_mapped_address = match XorMappedAddress::with_address(origin, key) {
+    fn request(header: &MessageHeader, body:&Attributes, connection: &Connection, server: &Server) -> Result<Vec<AttributeBody>, ErrorCodeType> {
+        let mapped_address = connection.mapped_address();
+        let key = xor_mapped_address::address_key(&mapped_address, &header.id);
+        let xor_mapped_address = match XorMappedAddress::with_address(mapped_address, key) {
             Ok(xor_mapped_address) => xor_mapped_address,
-            Err(_) => return Err(()),
+            Err(_) => return Err(ErrorCodeType::ServerError),
         };
-        let body = attributes::serialise_attribute(&AttributeBody::XorMappedAddress(xor_mapped_address));
-        Ok(Some(body))
+        let mut response = vec![AttributeBody::XorMappedAddress(xor_mapped_address)];
+
+        // NAT behaviour discovery attributes (RFC 5780)
+        if let Some(discovery) = discovery_over(connection, server) {
+            let origin = match route(body, connection, server) {
+                Some(route) => route.tuple.server,
+                None => connection.tuple.server,
+            };
+            let other_address = discovery.other_address(&connection.tuple.server);
+            response.push(AttributeBody::ResponseOrigin(ResponseOrigin::with_address(origin)));
+            response.push(AttributeBody::OtherAddress(OtherAddress::with_address(other_address)));
+
+            if let Some(AttributeBody::Padding(padding)) = body.get(MessageAttribute::Padding) {
+                if let Some(length) = padding.length() {
+                    response.push(AttributeBody::Padding(Padding::with_length(length)));
+                }
+            }
+        }
+
+        Ok(response)
+    }
+}
+
+// CHANGE-REQUEST and RESPONSE-PORT can only be honoured over UDP with an alternate address,
+// and only on the sockets bound for it
+pub fn discovery_over<'a>(connection: &Connection, server: &'a Server) -> Option<&'a Discovery> {
+    match (&server.discovery, connection.tuple.transport) {
+        (Some(discovery), Transport::Udp) if discovery.serves(&connection.tuple.server) => Some(discovery),
+        _ => None,
+    }
+}
+
+// The connection a response should go out on instead of the one the request came in on,
+// if CHANGE-REQUEST or RESPONSE-PORT ask for one
+pub fn route(body: &Attributes, connection: &Connection, server: &Server) -> Option<Connection> {
+    let discovery = discovery_over(connection, server)?;
+
+    let (change_ip, change_port) = match body.get(MessageAttribute::ChangeRequest) {
+        Some(AttributeBody::ChangeRequest(change_request)) => (change_request.change_ip(), change_request.change_port()),
+        _ => (false, false),
+    };
+    let port = match body.get(MessageAttribute::ResponsePort) {
+        Some(AttributeBody::ResponsePort(port)) => port.value(),
+        _ => None,
+    };
+    if !change_ip && !change_port && port.is_none() {
+        return None;
     }
+
+    let (socket, origin) = discovery.origin(&connection.tuple.server, change_ip, change_port)?;
+    let client = match port {
+        Some(port) => SocketAddr::new(connection.tuple.client.ip(), port),
+        None => connection.tuple.client,
+    };
+    Some(Connection::udp(socket, client, origin))
 }
@@ -0,0 +1,32 @@
+use crate::attributes::{ Attributes, MessageAttribute, AttributeBody };
+use crate::attributes::error_code::ErrorCodeType;
+use crate::handlers::{ self, MessageHandler };
+use crate::header::MessageHeader;
+use crate::server::Server;
+use crate::transport::Connection;
+
+pub struct ChannelBind {
+}
+impl MessageHandler for ChannelBind {
+    fn indication(_header: &MessageHeader, _body:&Attributes, _connection: &Connection, _server: &Server) {
+    }
+    fn request(_header: &MessageHeader, body:&Attributes, connection: &Connection, server: &Server) -> Result<Vec<AttributeBody>, ErrorCodeType> {
+        let username = handlers::username(body);
+        let channel = match body.get(MessageAttribute::ChannelNumber) {
+            Some(AttributeBody::ChannelNumber(channel)) => channel.value(),
+            _ => None,
+        };
+        let peer = match body.get(MessageAttribute::XorPeerAddress) {
+            Some(AttributeBody::XorPeerAddress(peer)) => peer.address(),
+            _ => None,
+        };
+        let (channel, peer) = match (channel, peer) {
+            (Some(channel), Some(peer)) => (channel, peer),
+            _ => return Err(ErrorCodeType::BadRequest),
+        };
+
+        server.allocations.bind_channel(&connection.tuple, channel, &peer, username)?;
+
+        Ok(vec![])
+    }
+}
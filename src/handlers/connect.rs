@@ -0,0 +1,29 @@
+use crate::attributes::{ Attributes, MessageAttribute, AttributeBody };
+use crate::attributes::connection_id::ConnectionId;
+use crate::attributes::error_code::ErrorCodeType;
+use crate::handlers::{ self, MessageHandler };
+use crate::header::MessageHeader;
+use crate::server::Server;
+use crate::transport::Connection;
+
+pub struct Connect {
+}
+impl MessageHandler for Connect {
+    fn indication(_header: &MessageHeader, _body:&Attributes, _connection: &Connection, _server: &Server) {
+    }
+    fn request(_header: &MessageHeader, body:&Attributes, connection: &Connection, server: &Server) -> Result<Vec<AttributeBody>, ErrorCodeType> {
+        let username = handlers::username(body);
+        let peer = match body.get(MessageAttribute::XorPeerAddress) {
+            Some(AttributeBody::XorPeerAddress(peer)) => peer.address(),
+            _ => None,
+        };
+        let peer = match peer {
+            Some(peer) => peer,
+            None => return Err(ErrorCodeType::BadRequest),
+        };
+
+        let connection_id = server.allocations.connect(&connection.tuple, &peer, username)?;
+
+        Ok(vec![AttributeBody::ConnectionId(ConnectionId::with_value(connection_id))])
+    }
+}
@@ -0,0 +1,33 @@
+use crate::attributes::{ Attributes, MessageAttribute, AttributeBody };
+use crate::attributes::error_code::ErrorCodeType;
+use crate::handlers::{ self, MessageHandler };
+use crate::header::MessageHeader;
+use crate::server::Server;
+use crate::transport::{ Connection, Transport };
+
+// Sent by the client on a new TCP connection, which carries the peer's data from then on
+pub struct ConnectionBind {
+}
+impl MessageHandler for ConnectionBind {
+    fn indication(_header: &MessageHeader, _body:&Attributes, _connection: &Connection, _server: &Server) {
+    }
+    fn request(_header: &MessageHeader, body:&Attributes, connection: &Connection, server: &Server) -> Result<Vec<AttributeBody>, ErrorCodeType> {
+        // Peer data is relayed onto TCP and TLS connections, as the allocation's own was (RFC 6062 section 4.3)
+        if !matches!(connection.tuple.transport, Transport::Tcp | Transport::Tls) {
+            return Err(ErrorCodeType::BadRequest);
+        }
+        let connection_id = match body.get(MessageAttribute::ConnectionId) {
+            Some(AttributeBody::ConnectionId(connection_id)) => connection_id.value(),
+            _ => None,
+        };
+        let connection_id = match connection_id {
+            Some(connection_id) => connection_id,
+            None => return Err(ErrorCodeType::BadRequest),
+        };
+
+        // Only the user who made the allocation may take its peer connections
+        server.allocations.bind_connection(&connection.tuple, connection_id, handlers::username(body))?;
+
+        Ok(vec![])
+    }
+}
@@ -0,0 +1,35 @@
+use crate::attributes::{ Attributes, MessageAttribute, AttributeBody };
+use crate::attributes::error_code::ErrorCodeType;
+use crate::handlers::{ self, MessageHandler };
+use crate::header::MessageHeader;
+use crate::server::Server;
+use crate::transport::Connection;
+
+pub struct CreatePermission {
+}
+impl MessageHandler for CreatePermission {
+    fn indication(_header: &MessageHeader, _body:&Attributes, _connection: &Connection, _server: &Server) {
+    }
+    fn request(_header: &MessageHeader, body:&Attributes, connection: &Connection, server: &Server) -> Result<Vec<AttributeBody>, ErrorCodeType> {
+        let username = handlers::username(body);
+        // A request may carry several XOR-PEER-ADDRESS attributes, and either all of the
+        // permissions are installed or none are
+        let mut peers = vec![];
+        for attribute in body.get_all(MessageAttribute::XorPeerAddress) {
+            match attribute {
+                AttributeBody::XorPeerAddress(peer) => match peer.address() {
+                    Some(peer) => peers.push(peer.ip()),
+                    None => return Err(ErrorCodeType::BadRequest),
+                },
+                _ => return Err(ErrorCodeType::BadRequest),
+            }
+        }
+        if peers.is_empty() {
+            return Err(ErrorCodeType::BadRequest);
+        }
+
+        server.allocations.permit(&connection.tuple, &peers, username)?;
+
+        Ok(vec![])
+    }
+}
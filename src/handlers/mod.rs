@@ -1,50 +1,260 @@
-use std::collections::HashMap;
-use std::net::SocketAddr;
-
-use crate::header::{ MessageHeader, MessageType, MessageClass };
-use crate::attributes::{ MessageAttribute, AttributeBody };
+use crate::auth::{ Authenticator, Mechanism };
+use crate::header::{ self, MessageHeader, MessageType, MessageClass };
+use crate::attributes::{ Attributes, MessageAttribute, AttributeBody };
+use crate::attributes::error_code::{ ErrorCode, ErrorCodeType };
+use crate::attributes::nonce::Nonce;
+use crate::attributes::realm::Realm;
+use crate::attributes::unknown_attributes::UnknownAttributes;
+use crate::message::{ Message, MessageBuilder };
+use crate::server::Server;
+use crate::transport::Connection;
 
+mod allocate;
+use allocate::Allocate;
 mod binding;
 use binding::Binding;
+mod channel_bind;
+use channel_bind::ChannelBind;
+mod connect;
+use connect::Connect;
+mod connection_bind;
+use connection_bind::ConnectionBind;
+mod create_permission;
+use create_permission::CreatePermission;
+mod refresh;
+use refresh::Refresh;
+mod send;
+use send::SendIndication;
 
 pub trait MessageHandler {
-    fn indication(header: &MessageHeader, body: &HashMap<MessageAttribute, AttributeBody>, origin: &SocketAddr);
-    fn request(header: &MessageHeader, body: &HashMap<MessageAttribute, AttributeBody>, origin: &SocketAddr) -> Result<Option<Vec<u8>>, ()>;
-}
-
-pub fn process_message(header: &MessageHeader, body: &HashMap<MessageAttribute, AttributeBody>, origin: &SocketAddr) -> Option<Vec<u8>> {
-    let mut body = match &header.mtype {
-        MessageType::Binding => {
-            match &header.mclass {
-                MessageClass::Request => {
-                    match Binding::request(header, body, origin) {
-                        Ok(res) => res,
-                        Err(_) => None,
-                    }
-                },
-                MessageClass::Indication => {
-                    Binding::indication(header, body, origin);
-                    None
+    fn indication(header: &MessageHeader, body: &Attributes, connection: &Connection, server: &Server);
+    fn request(header: &MessageHeader, body: &Attributes, connection: &Connection, server: &Server) -> Result<Vec<AttributeBody>, ErrorCodeType>;
+}
+
+pub fn process_message(message: &[u8], header: &MessageHeader, body: &Attributes, mut unknown_attributes: UnknownAttributes, connection: &Connection, server: &Server) -> Option<Vec<u8>> {
+    let authenticator = &server.authenticator;
+
+    // A bad FINGERPRINT means this is not a STUN message at all
+    if let Some(AttributeBody::Fingerprint(fingerprint)) = body.get(MessageAttribute::Fingerprint) {
+        if !fingerprint.verify(message) {
+            return None;
+        }
+    }
+
+    // Indications cannot be challenged, so only requests are authenticated
+    let key = match &header.mclass {
+        MessageClass::Request => match authenticator.authenticate(message, body) {
+            Ok(key) => key,
+            Err(code) => return challenge_response(header, code, authenticator),
+        },
+        _ => None,
+    };
+
+    // Without an alternate address CHANGE-REQUEST and RESPONSE-PORT cannot be honoured, so they are treated as unknown (RFC 5780 section 7.2)
+    if header.mtype == MessageType::Binding && binding::discovery_over(connection, server).is_none() {
+        if body.contains(MessageAttribute::ChangeRequest) {
+            unknown_attributes.add(0x0003);
+        }
+        if body.contains(MessageAttribute::ResponsePort) {
+            unknown_attributes.add(0x0027);
+        }
+    }
+
+    // Unknown comprehension-required attributes only matter in requests, and are checked after authentication
+    if !unknown_attributes.is_empty() {
+        let attributes = vec![AttributeBody::UnknownAttributes(unknown_attributes)];
+        return error_response(header, ErrorCodeType::UnknownAttribute, attributes, key.as_deref());
+    }
+
+    let result = match &header.mtype {
+        MessageType::Binding => handle::<Binding>(header, body, connection, server)?,
+        MessageType::Allocate => handle::<Allocate>(header, body, connection, server)?,
+        MessageType::Refresh => handle::<Refresh>(header, body, connection, server)?,
+        MessageType::Send => handle::<SendIndication>(header, body, connection, server)?,
+        MessageType::CreatePermission => handle::<CreatePermission>(header, body, connection, server)?,
+        MessageType::ChannelBind => handle::<ChannelBind>(header, body, connection, server)?,
+        MessageType::Connect => handle::<Connect>(header, body, connection, server)?,
+        MessageType::ConnectionBind => handle::<ConnectionBind>(header, body, connection, server)?,
+        // Data and ConnectionAttempt indications only ever flow from the server to the client
+        MessageType::Data | MessageType::ConnectionAttempt => Err(ErrorCodeType::BadRequest),
+    };
+
+    match result {
+        Ok(response) => {
+            let response = build_response(header.mtype, MessageClass::Success, &header.id, response, key.as_deref());
+            // A Binding response may have to be sent from an alternate address or to another port
+            if header.mtype == MessageType::Binding {
+                if let Some(route) = binding::route(body, connection, server) {
+                    let _ = route.send(&response);
+                    return None;
                 }
-                _ => None,
             }
+            Some(response)
         },
-    };
+        Err(code) => error_response(header, code, vec![], key.as_deref()),
+    }
+}
 
-    let response = if let Some(mut body) = body {
-        let response_header = MessageHeader {
-            mtype: header.mtype,
-            mclass: header.mclass,
-            length: body.len() as u16,
-            id: header.id.clone(),
-        };
+// Indications never produce a response
+fn handle<H: MessageHandler>(header: &MessageHeader, body: &Attributes, connection: &Connection, server: &Server) -> Option<Result<Vec<AttributeBody>, ErrorCodeType>> {
+    match &header.mclass {
+        MessageClass::Request => Some(H::request(header, body, connection, server)),
+        MessageClass::Indication => {
+            H::indication(header, body, connection, server);
+            None
+        },
+        _ => None,
+    }
+}
 
-        let mut response = response_header.serialise();
-        response.append(&mut body);
-        Some(response)
+// The user a request was authenticated as, which an allocation belongs to. Requests go
+// unauthenticated only when there are no credentials, and then there are no allocations.
+fn username(body: &Attributes) -> &str {
+    match body.get(MessageAttribute::Username) {
+        Some(AttributeBody::Username(username)) => username.value().unwrap_or(""),
+        _ => "",
+    }
+}
+
+// Only requests are answered; errors in indications and responses are silently discarded
+pub fn error_response(header: &MessageHeader, code: ErrorCodeType, attributes: Vec<AttributeBody>, key: Option<&[u8]>) -> Option<Vec<u8>> {
+    match &header.mclass {
+        MessageClass::Request => (),
+        _ => return None,
+    }
+
+    let mut body = vec![AttributeBody::ErrorCode(ErrorCode::with_type(code))];
+    body.extend(attributes);
+
+    Some(build_response(header.mtype, MessageClass::Error, &header.id, body, key))
+}
+
+// RFC 5389 section 7.3.1: a request with an unknown method gets a 400, while anything
+// else with one is dropped
+pub fn unknown_method_response(message: &[u8], method: u16) -> Option<Vec<u8>> {
+    match header::get_message_class(message[0], message[1]) {
+        MessageClass::Request => (),
+        _ => return None,
+    }
+    let mut id = [0; 12];
+    id.copy_from_slice(&message[8..20]);
+
+    let error_code = AttributeBody::ErrorCode(ErrorCode::with_type(ErrorCodeType::BadRequest));
+    Some(MessageBuilder::with_method(method, MessageClass::Error).transaction_id(&id)
+                                                                 .attribute(error_code)
+                                                                 .fingerprint()
+                                                                 .build())
+}
+
+pub fn build_indication(mtype: MessageType, id: &[u8; 12], body: Vec<AttributeBody>) -> Vec<u8> {
+    build_response(mtype, MessageClass::Indication, id, body, None)
+}
+
+// Appends MESSAGE-INTEGRITY if there is a key, then FINGERPRINT
+fn build_response(mtype: MessageType, mclass: MessageClass, id: &[u8; 12], body: Vec<AttributeBody>, key: Option<&[u8]>) -> Vec<u8> {
+    let mut builder = Message::builder(mtype, mclass).transaction_id(id)
+                                                      .attributes(body);
+    if let Some(key) = key {
+        builder = builder.message_integrity(key);
+    }
+
+    builder.fingerprint().build()
+}
+
+fn challenge_response(header: &MessageHeader, code: ErrorCodeType, authenticator: &Authenticator) -> Option<Vec<u8>> {
+    let challenge = code == ErrorCodeType::Unauthorised || code == ErrorCodeType::StaleNonce;
+    let attributes = if challenge && authenticator.mechanism() == Mechanism::LongTerm {
+        vec![
+            AttributeBody::Realm(Realm::with_value(String::from(authenticator.realm()))),
+            AttributeBody::Nonce(Nonce::with_value(authenticator.generate_nonce())),
+        ]
     } else {
-        None
+        vec![]
     };
 
-    response
+    error_response(header, code, attributes, None)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::net::{ IpAddr, SocketAddr, UdpSocket };
+    use std::sync::Arc;
+    use crate::allocations::Allocations;
+    use crate::attributes::username::Username;
+
+    fn server(mechanism:Mechanism) -> Server {
+        let mut users = HashMap::new();
+        users.insert("alice".to_string(), "secret".to_string());
+        Server {
+            authenticator: Authenticator::new(mechanism, "example.org".to_string(), users, vec![]),
+            allocations: Allocations::new(IpAddr::from([127, 0, 0, 1]), vec![], None, None),
+            discovery: None,
+        }
+    }
+
+    // Runs a message through process_message as the server does with what arrives over UDP
+    fn process(message:&[u8], server:&Server) -> Option<Vec<u8>> {
+        let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").unwrap());
+        let connection = Connection::udp(Arc::clone(&socket), SocketAddr::from(([127, 0, 0, 1], 5000)), socket.local_addr().unwrap());
+        let message = Message::decode(message).unwrap();
+        let attributes = message.decode_attributes().unwrap();
+        process_message(message.raw(), &message.header, &attributes, message.unknown_attributes(), &connection, server)
+    }
+
+    #[test]
+    fn short_term_responses_are_protected_with_the_password() {
+        let server = server(Mechanism::ShortTerm);
+        let request = MessageBuilder::new(MessageType::Binding, MessageClass::Request)
+            .attribute(AttributeBody::Username(Username::with_value("alice".to_string())))
+            .message_integrity(b"secret")
+            .build();
+
+        let response = process(&request, &server).unwrap();
+        let response = Message::decode(&response).unwrap();
+        assert!(matches!(response.header.mclass, MessageClass::Success));
+        let integrity = match response.get(MessageAttribute::MessageIntegrity) {
+            Ok(Some(AttributeBody::MessageIntegrity(integrity))) => integrity,
+            _ => panic!("no MESSAGE-INTEGRITY"),
+        };
+        assert!(integrity.verify(response.raw(), b"secret"));
+        assert!(!integrity.verify(response.raw(), b"wrong"));
+    }
+
+    // The error code of a challenge, which must carry REALM and a NONCE to retry with, and cannot
+    // carry MESSAGE-INTEGRITY as there is no key the client has proven it holds
+    fn challenge(response:&[u8]) -> u16 {
+        let response = Message::decode(response).unwrap();
+        assert!(matches!(response.header.mclass, MessageClass::Error));
+        match response.get(MessageAttribute::Realm) {
+            Ok(Some(AttributeBody::Realm(realm))) => assert_eq!(realm.value(), Some("example.org")),
+            _ => panic!("no REALM"),
+        }
+        assert!(matches!(response.get(MessageAttribute::Nonce), Ok(Some(AttributeBody::Nonce(_)))));
+        assert!(matches!(response.get(MessageAttribute::MessageIntegrity), Ok(None)));
+        match response.get(MessageAttribute::ErrorCode) {
+            Ok(Some(AttributeBody::ErrorCode(error_code))) => error_code.code().unwrap(),
+            _ => panic!("no ERROR-CODE"),
+        }
+    }
+
+    #[test]
+    fn long_term_challenges() {
+        let server = server(Mechanism::LongTerm);
+
+        let request = MessageBuilder::new(MessageType::Allocate, MessageClass::Request).build();
+        assert_eq!(challenge(&process(&request, &server).unwrap()), 401);
+
+        // A nonce from another server is as stale as an expired one
+        let nonce = Authenticator::new(Mechanism::LongTerm, "example.org".to_string(), HashMap::new(), vec![]).generate_nonce();
+        let request = MessageBuilder::new(MessageType::Allocate, MessageClass::Request)
+            .attribute(AttributeBody::Username(Username::with_value("alice".to_string())))
+            .attribute(AttributeBody::Realm(Realm::with_value("example.org".to_string())))
+            .attribute(AttributeBody::Nonce(Nonce::with_value(nonce)))
+            .message_integrity(b"key")
+            .build();
+        assert_eq!(challenge(&process(&request, &server).unwrap()), 438);
+    }
+}
+
@@ -0,0 +1,25 @@
+use crate::attributes::{ Attributes, MessageAttribute, AttributeBody };
+use crate::attributes::error_code::ErrorCodeType;
+use crate::attributes::lifetime::Lifetime;
+use crate::handlers::{ self, MessageHandler };
+use crate::header::MessageHeader;
+use crate::server::Server;
+use crate::transport::Connection;
+
+pub struct Refresh {
+}
+impl MessageHandler for Refresh {
+    fn indication(_header: &MessageHeader, _body:&Attributes, _connection: &Connection, _server: &Server) {
+    }
+    fn request(_header: &MessageHeader, body:&Attributes, connection: &Connection, server: &Server) -> Result<Vec<AttributeBody>, ErrorCodeType> {
+        let username = handlers::username(body);
+        let lifetime = match body.get(MessageAttribute::Lifetime) {
+            Some(AttributeBody::Lifetime(lifetime)) => lifetime.value(),
+            _ => None,
+        };
+
+        let lifetime = server.allocations.refresh(&connection.tuple, lifetime, username)?;
+
+        Ok(vec![AttributeBody::Lifetime(Lifetime::with_value(lifetime))])
+    }
+}
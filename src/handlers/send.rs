@@ -0,0 +1,29 @@
+use crate::attributes::{ Attributes, MessageAttribute, AttributeBody };
+use crate::attributes::error_code::ErrorCodeType;
+use crate::handlers::MessageHandler;
+use crate::header::MessageHeader;
+use crate::server::Server;
+use crate::transport::Connection;
+
+// Send only exists as an indication
+pub struct SendIndication {
+}
+impl MessageHandler for SendIndication {
+    fn indication(_header: &MessageHeader, body:&Attributes, connection: &Connection, server: &Server) {
+        let peer = match body.get(MessageAttribute::XorPeerAddress) {
+            Some(AttributeBody::XorPeerAddress(peer)) => peer.address(),
+            _ => None,
+        };
+        let data = match body.get(MessageAttribute::Data) {
+            Some(AttributeBody::Data(data)) => data.value(),
+            _ => None,
+        };
+
+        if let (Some(peer), Some(data)) = (peer, data) {
+            server.allocations.send(&connection.tuple, &peer, data);
+        }
+    }
+    fn request(_header: &MessageHeader, _body:&Attributes, _connection: &Connection, _server: &Server) -> Result<Vec<AttributeBody>, ErrorCodeType> {
+        Err(ErrorCodeType::BadRequest)
+    }
+}
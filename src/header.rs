@@ -1,6 +1,49 @@
-#[derive(Copy, Clone)]
+use crate::error::DecodeError;
+
+#[derive(Copy, Clone, PartialEq)]
 pub enum MessageType {
-    Binding
+    Binding,
+    Allocate,
+    Refresh,
+    Send,
+    Data,
+    CreatePermission,
+    ChannelBind,
+    Connect,
+    ConnectionBind,
+    ConnectionAttempt,
+}
+impl MessageType {
+    pub(crate) fn method(&self) -> u16 {
+        match self {
+            MessageType::Binding => 0x001,
+            MessageType::Allocate => 0x003,
+            MessageType::Refresh => 0x004,
+            MessageType::Send => 0x006,
+            MessageType::Data => 0x007,
+            MessageType::CreatePermission => 0x008,
+            MessageType::ChannelBind => 0x009,
+            MessageType::Connect => 0x00A,
+            MessageType::ConnectionBind => 0x00B,
+            MessageType::ConnectionAttempt => 0x00C,
+        }
+    }
+
+    fn from_method(method:u16) -> Option<MessageType> {
+        match method {
+            0x001 => Some(MessageType::Binding),
+            0x003 => Some(MessageType::Allocate),
+            0x004 => Some(MessageType::Refresh),
+            0x006 => Some(MessageType::Send),
+            0x007 => Some(MessageType::Data),
+            0x008 => Some(MessageType::CreatePermission),
+            0x009 => Some(MessageType::ChannelBind),
+            0x00A => Some(MessageType::Connect),
+            0x00B => Some(MessageType::ConnectionBind),
+            0x00C => Some(MessageType::ConnectionAttempt),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -19,48 +62,58 @@ pub struct MessageHeader {
 }
 impl MessageHeader {
     pub fn serialise(&self) -> Vec<u8> {
-        let mut message_type:u16 = match &self.mtype {
-            MessageType::Binding => 1,
-        };
-        message_type |= match &self.mclass {
-            MessageClass::Request => 0b0000000000000000,
-            MessageClass::Indication => 0b0000000000010000,
-            MessageClass::Success => 0b0000000100000000,
-            MessageClass::Error => 0b0000000100010000,
-        };
+        serialise_header(self.mtype.method(), self.mclass, self.length, &self.id)
+    }
+}
 
-        let mut serialised_header = message_type.to_be_bytes().to_vec();
-        let length_bytes = self.length.to_be_bytes();
-        serialised_header.push(length_bytes[0]);
-        serialised_header.push(length_bytes[1]);
-        let mut id = self.id.to_vec();
-        serialised_header.append(&mut id);
+// Methods are passed as numbers so that requests with methods there is no MessageType
+// for can still be answered
+pub(crate) fn serialise_header(method:u16, mclass:MessageClass, length:u16, id:&[u8; 12]) -> Vec<u8> {
+    // The class bits are interleaved with the method bits: M11-M7 C1 M6-M4 C0 M3-M0
+    let mut message_type:u16 = ((method & 0x0F80) << 2) | ((method & 0x0070) << 1) | (method & 0x000F);
+    message_type |= match mclass {
+        MessageClass::Request => 0b0000000000000000,
+        MessageClass::Indication => 0b0000000000010000,
+        MessageClass::Success => 0b0000000100000000,
+        MessageClass::Error => 0b0000000100010000,
+    };
 
-        serialised_header
-    }
+    let mut serialised_header = message_type.to_be_bytes().to_vec();
+    let length_bytes = length.to_be_bytes();
+    serialised_header.push(length_bytes[0]);
+    serialised_header.push(length_bytes[1]);
+    serialised_header.append(&mut vec![0x21, 0x12, 0xA4, 0x42]);
+    let mut id = id.to_vec();
+    serialised_header.append(&mut id);
+
+    serialised_header
 }
 
-pub fn verify_header(header:&[u8]) -> Result<MessageHeader, u32> {
+pub fn verify_header(header:&[u8]) -> Result<MessageHeader, DecodeError> {
     // Check header length is 20 bytes
-    if header.len() != 20 {
-        return Err(1);
+    if header.len() < 20 {
+        return Err(DecodeError::Truncated);
+    }
+    if header.len() > 20 {
+        return Err(DecodeError::BadLength);
     }
 
     // Verify first two bits is 0
     if header[0] >> 6 != 0 {
-        return Err(1);
+        return Err(DecodeError::NotStun);
     }
 
     // Check magic cookie
     if !(header[4] == 0x21 && header[5] == 0x12 && header[6] == 0xA4 && header[7] == 0x42) {
-        return Err(1);
+        return Err(DecodeError::BadMagicCookie);
     }
 
     // Parse header
     let mclass = get_message_class(header[0], header[1]);
-    let mtype = match get_message_type(header[0], header[1]) {
+    let method = get_method(header[0], header[1]);
+    let mtype = match MessageType::from_method(method) {
         Some(mtype) => mtype,
-        None => return Err(1),
+        None => return Err(DecodeError::UnknownMethod(method)),
     };
     let length = 256 * (header[2] as u16) + (header[3] as u16);
     let mut id = [0; 12];
@@ -75,7 +128,7 @@ pub fn verify_header(header:&[u8]) -> Result<MessageHeader, u32> {
     Ok(header)
 }
 
-fn get_message_class(b1: u8, b2: u8) -> MessageClass {
+pub(crate) fn get_message_class(b1: u8, b2: u8) -> MessageClass {
     let cb1 = (b1 & 1) != 0;
     let cb2 = (b2 & 16) != 0;
     match (cb1, cb2) {
@@ -86,13 +139,8 @@ fn get_message_class(b1: u8, b2: u8) -> MessageClass {
     }
 }
 
-fn get_message_type(b1: u8, b2: u8) -> Option<MessageType> {
-    // Check for Binding
-    let p1 = b1 == 1 || b1 == 0;
-    let p2 = b2 == 17 || b2 == 1;
-    if p1 && p2 {
-        Some(MessageType::Binding)
-    } else {
-        None
-    }
+fn get_method(b1: u8, b2: u8) -> u16 {
+    // Strip out the class bits
+    let message_type = u16::from_be_bytes([b1, b2]);
+    ((message_type & 0x3E00) >> 2) | ((message_type & 0x00E0) >> 1) | (message_type & 0x000F)
 }
@@ -0,0 +1,32 @@
+// STUN message codecs and a client built on them
+pub mod attributes;
+pub mod client;
+pub mod error;
+pub mod header;
+pub mod message;
+
+// The STUN and TURN server
+#[cfg(feature = "server")]
+pub mod config;
+#[cfg(feature = "server")]
+pub mod server;
+
+#[cfg(feature = "server")]
+mod allocations;
+#[cfg(feature = "server")]
+mod auth;
+// Shared with the client, which needs only some of each
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+mod channel_data;
+#[cfg(feature = "server")]
+mod discovery;
+#[cfg_attr(not(feature = "server"), allow(dead_code))]
+mod framing;
+#[cfg(feature = "server")]
+mod handlers;
+#[cfg(feature = "server")]
+mod pool;
+#[cfg(feature = "server")]
+mod tls;
+#[cfg(feature = "server")]
+mod transport;
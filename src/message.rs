@@ -0,0 +1,283 @@
+extern crate rand;
+
+use crate::attributes::{ self, AttributeBody, Attributes, MessageAttribute };
+use crate::attributes::fingerprint::Fingerprint;
+use crate::attributes::message_integrity::MessageIntegrity;
+use crate::attributes::registry::Registry;
+use crate::attributes::unknown_attributes::UnknownAttributes;
+use crate::error::DecodeError;
+use crate::header::{ self, MessageClass, MessageHeader, MessageType };
+
+// An attribute as it appears on the wire, borrowed from the message it was decoded from
+pub struct RawAttribute<'a> {
+    pub type_code: u16,
+    pub value: &'a [u8],
+    // Offset of the attribute from the start of the message body
+    pub offset: usize,
+}
+
+// A decoded message. Attribute values are borrowed from the buffer the message was
+// decoded from, and only decoded into attribute types when looked up.
+pub struct Message<'a> {
+    pub header: MessageHeader,
+    // The message itself, header included, without anything that followed it in the buffer
+    raw: &'a [u8],
+    // Only the attributes that count: FINGERPRINT is always last, and nothing but it may
+    // follow MESSAGE-INTEGRITY
+    attributes: Vec<RawAttribute<'a>>,
+    // Comprehension-required attributes (0x0000-0x7FFF) without a type to decode them into
+    unknown_attributes: Vec<u16>,
+    // Attribute types to decode besides the built-in ones
+    registry: Option<&'a Registry>,
+}
+impl<'a> Message<'a> {
+    pub fn builder(mtype:MessageType, mclass:MessageClass) -> MessageBuilder {
+        MessageBuilder::new(mtype, mclass)
+    }
+
+    // Fails if the header is invalid, or the message or any attribute in it is truncated
+    pub fn decode(message:&'a [u8]) -> Result<Message<'a>, DecodeError> {
+        Message::decode_registry(message, None)
+    }
+
+    // As decode, with the attribute types in the registry decoded into their own types
+    pub fn decode_with(message:&'a [u8], registry:&'a Registry) -> Result<Message<'a>, DecodeError> {
+        Message::decode_registry(message, Some(registry))
+    }
+
+    fn decode_registry(message:&'a [u8], registry:Option<&'a Registry>) -> Result<Message<'a>, DecodeError> {
+        if message.len() < 20 {
+            return Err(DecodeError::Truncated);
+        }
+        let header = header::verify_header(&message[0..20])?;
+        let length = 20 + header.length as usize;
+        if message.len() < length {
+            return Err(DecodeError::Truncated);
+        }
+        let (attributes, unknown_attributes) = read_attributes(&message[20..length], registry)?;
+
+        Ok(Message {
+            header,
+            raw: &message[0..length],
+            attributes,
+            unknown_attributes,
+            registry,
+        })
+    }
+
+    pub fn raw(&self) -> &'a [u8] {
+        self.raw
+    }
+
+    // Every attribute that counts, in the order they appear in the message
+    pub fn attributes(&self) -> &[RawAttribute<'a>] {
+        &self.attributes
+    }
+
+    // The comprehension-required attributes there is no type for, which a request
+    // has to be rejected for
+    pub fn unknown_attributes(&self) -> UnknownAttributes {
+        let mut unknown_attributes = UnknownAttributes::new_empty();
+        for type_code in &self.unknown_attributes {
+            unknown_attributes.add(*type_code);
+        }
+        unknown_attributes
+    }
+
+    // Decodes every attribute, failing if any known one is malformed
+    pub fn decode_attributes(&self) -> Result<Attributes, DecodeError> {
+        Attributes::decode(&self.attributes, &self.header, self.registry)
+    }
+
+    // Decodes the first attribute of the given type; fails if it is malformed
+    pub fn get(&self, attribute:MessageAttribute) -> Result<Option<AttributeBody>, DecodeError> {
+        match self.raw_attributes(attribute).next() {
+            Some(raw) => self.decode_attribute(raw).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // Decodes every attribute of the given type in order; fails if any is malformed
+    pub fn get_all(&self, attribute:MessageAttribute) -> Result<Vec<AttributeBody>, DecodeError> {
+        self.raw_attributes(attribute).map(|raw| self.decode_attribute(raw)).collect()
+    }
+
+    // Attributes are told apart by type code, so only those looked up are decoded
+    fn raw_attributes(&self, attribute:MessageAttribute) -> impl Iterator<Item = &RawAttribute<'a>> {
+        let registry = self.registry;
+        self.attributes.iter().filter(move |raw| attributes::attribute_type(raw.type_code, registry) == attribute)
+    }
+
+    fn decode_attribute(&self, raw:&RawAttribute) -> Result<AttributeBody, DecodeError> {
+        attributes::decode_attribute(raw.type_code, raw.value, &self.header, raw.offset, self.registry).map(|(_, body)| body)
+    }
+}
+
+// Splits a message body into its attributes, keeping only those that count, and notes the
+// comprehension-required ones that are unknown. Fails if any attribute is truncated.
+pub(crate) fn read_attributes<'a>(body:&'a [u8], registry:Option<&Registry>) -> Result<(Vec<RawAttribute<'a>>, Vec<u16>), DecodeError> {
+    let mut attributes = vec![];
+    let mut unknown_attributes = vec![];
+    let mut integrity = false;
+    let mut i = 0;
+    while i < body.len() {
+        if body.len() < i + 4 {
+            return Err(DecodeError::Truncated);
+        }
+        let type_code = u16::from_be_bytes([body[i], body[i + 1]]);
+        let value_length = u16::from_be_bytes([body[i + 2], body[i + 3]]) as usize;
+        if body.len() < i + 4 + value_length {
+            return Err(DecodeError::Truncated);
+        }
+        let raw = RawAttribute {
+            type_code,
+            value: &body[(i + 4)..(i + 4 + value_length)],
+            offset: i,
+        };
+        // Values are padded to a multiple of four bytes
+        i += 4 + value_length.div_ceil(4) * 4;
+
+        match attributes::attribute_type(type_code, registry) {
+            MessageAttribute::Fingerprint => {
+                attributes.push(raw);
+                break;
+            },
+            _ if integrity => continue,
+            MessageAttribute::MessageIntegrity => integrity = true,
+            MessageAttribute::Unknown(type_code) if type_code < 0x8000 => unknown_attributes.push(type_code),
+            _ => (),
+        }
+        attributes.push(raw);
+    }
+
+    Ok((attributes, unknown_attributes))
+}
+
+// Puts a message together attribute by attribute, keeping the header length up to date
+pub struct MessageBuilder {
+    method: u16,
+    mclass: MessageClass,
+    id: [u8; 12],
+    body: Vec<u8>,
+}
+impl MessageBuilder {
+    // The transaction ID is random unless one is given
+    pub fn new(mtype:MessageType, mclass:MessageClass) -> MessageBuilder {
+        MessageBuilder::with_method(mtype.method(), mclass)
+    }
+
+    // For answering a request with a method there is no MessageType for
+    pub(crate) fn with_method(method:u16, mclass:MessageClass) -> MessageBuilder {
+        MessageBuilder {
+            method,
+            mclass,
+            id: rand::random::<[u8; 12]>(),
+            body: vec![],
+        }
+    }
+
+    pub fn transaction_id(mut self, id:&[u8; 12]) -> MessageBuilder {
+        self.id = *id;
+        self
+    }
+
+    pub fn attribute(mut self, attribute:AttributeBody) -> MessageBuilder {
+        self.body.append(&mut attributes::serialise_attribute(&attribute));
+        self
+    }
+
+    pub fn attributes<I: IntoIterator<Item = AttributeBody>>(self, attributes:I) -> MessageBuilder {
+        attributes.into_iter().fold(self, |builder, attribute| builder.attribute(attribute))
+    }
+
+    // Covers every attribute added so far
+    pub fn message_integrity(self, key:&[u8]) -> MessageBuilder {
+        let integrity = MessageIntegrity::with_message(&self.serialise(), key);
+        self.attribute(AttributeBody::MessageIntegrity(integrity))
+    }
+
+    // Covers every attribute added so far, so should come last
+    pub fn fingerprint(self) -> MessageBuilder {
+        let fingerprint = Fingerprint::with_message(&self.serialise());
+        self.attribute(AttributeBody::Fingerprint(fingerprint))
+    }
+
+    pub fn build(self) -> Vec<u8> {
+        self.serialise()
+    }
+
+    fn serialise(&self) -> Vec<u8> {
+        let mut message = header::serialise_header(self.method, self.mclass, self.body.len() as u16, &self.id);
+        message.extend_from_slice(&self.body);
+        message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(attributes:&[(u16, &[u8])]) -> Vec<u8> {
+        let mut body = vec![];
+        for (type_code, value) in attributes {
+            body.extend_from_slice(&type_code.to_be_bytes());
+            body.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            body.extend_from_slice(value);
+            body.resize(body.len().div_ceil(4) * 4, 0);
+        }
+        let mut message = vec![0x00, 0x01];
+        message.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        message.extend_from_slice(&[0x21, 0x12, 0xA4, 0x42]);
+        message.extend_from_slice(&[0; 12]);
+        message.extend_from_slice(&body);
+        message
+    }
+
+    #[test]
+    fn only_fingerprint_counts_after_message_integrity() {
+        let message = request(&[(0x0006, b"user"), (0x0008, &[0; 20]), (0x0006, b"late"), (0x7FFF, b""), (0x8028, &[0; 4]), (0x0006, b"after")]);
+        let message = Message::decode(&message).unwrap();
+        let type_codes = message.attributes().iter().map(|raw| raw.type_code).collect::<Vec<u16>>();
+        assert_eq!(type_codes, vec![0x0006, 0x0008, 0x8028]);
+        assert!(message.unknown_attributes().is_empty());
+    }
+
+    #[test]
+    fn unknown_comprehension_required_attributes_are_collected() {
+        let message = request(&[(0x7FFF, b"x"), (0xBFFF, b"y")]);
+        let message = Message::decode(&message).unwrap();
+        assert!(!message.unknown_attributes().is_empty());
+        let (_, unknown_attributes) = attributes::get_attributes(&message.raw()[20..], &message.header).unwrap();
+        assert!(!unknown_attributes.is_empty());
+    }
+
+    #[test]
+    fn malformed_attributes_fail_lookup_like_get_attributes() {
+        // A LIFETIME value is four bytes
+        let message = request(&[(0x0006, b"user"), (0x000D, &[0; 3])]);
+        let message = Message::decode(&message).unwrap();
+        assert!(matches!(message.get(MessageAttribute::Username), Ok(Some(AttributeBody::Username(_)))));
+        assert!(message.get(MessageAttribute::Lifetime).is_err());
+        assert!(message.decode_attributes().is_err());
+        assert!(attributes::get_attributes(&message.raw()[20..], &message.header).is_err());
+    }
+
+    #[test]
+    fn unknown_attributes_round_trip() {
+        // A vendor attribute whose value needs three bytes of padding
+        let message = request(&[(0x0006, b"user"), (0x8070, b"acme!")]);
+        let decoded = Message::decode(&message).unwrap();
+        let unknown = decoded.get(MessageAttribute::Unknown(0x8070)).unwrap().unwrap();
+        match &unknown {
+            AttributeBody::Unknown { type_code, value } => assert_eq!((*type_code, &value[..]), (0x8070, &b"acme!"[..])),
+            _ => panic!("not kept as unknown"),
+        }
+
+        let rebuilt = MessageBuilder::new(MessageType::Binding, MessageClass::Request)
+            .transaction_id(&decoded.header.id)
+            .attribute(decoded.get(MessageAttribute::Username).unwrap().unwrap())
+            .attribute(unknown)
+            .build();
+        assert_eq!(rebuilt, message);
+    }
+}
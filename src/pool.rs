@@ -0,0 +1,67 @@
+use std::panic::{ self, AssertUnwindSafe };
+use std::sync::{ Arc, Mutex };
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use std::sync::mpsc::{ self, Receiver, SyncSender };
+use std::thread;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// A fixed number of threads that jobs are handed to. Jobs never wait for a thread to
+// free up: one is turned away instead when every thread is busy.
+pub struct WorkerPool {
+    workers: usize,
+    // Jobs handed out and not yet finished
+    busy: Arc<AtomicUsize>,
+    sender: SyncSender<Job>,
+}
+impl WorkerPool {
+    pub fn new(workers:usize) -> WorkerPool {
+        let (sender, receiver) = mpsc::sync_channel::<Job>(workers);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let busy = Arc::new(AtomicUsize::new(0));
+        for _ in 0..workers {
+            let receiver = Arc::clone(&receiver);
+            let busy = Arc::clone(&busy);
+            thread::spawn(move || work(receiver, busy));
+        }
+
+        WorkerPool {
+            workers,
+            busy,
+            sender,
+        }
+    }
+
+    // Fails if no thread is free to take the job
+    pub fn execute<F: FnOnce() + Send + 'static>(&self, job:F) -> Result<(), ()> {
+        let workers = self.workers;
+        if self.busy.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |busy| if busy < workers { Some(busy + 1) } else { None }).is_err() {
+            return Err(());
+        }
+
+        match self.sender.try_send(Box::new(job)) {
+            Ok(_) => Ok(()),
+            Err(_) => {
+                self.busy.fetch_sub(1, Ordering::SeqCst);
+                Err(())
+            },
+        }
+    }
+}
+
+fn work(receiver:Arc<Mutex<Receiver<Job>>>, busy:Arc<AtomicUsize>) {
+    loop {
+        let job = match receiver.lock() {
+            Ok(receiver) => receiver.recv(),
+            Err(_) => return,
+        };
+        match job {
+            // A job that panics takes down neither the thread nor the pool
+            Ok(job) => {
+                let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                busy.fetch_sub(1, Ordering::SeqCst);
+            },
+            Err(_) => return,
+        }
+    }
+}
@@ -0,0 +1,430 @@
+extern crate openssl;
+extern crate socket2;
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{ SocketAddr, TcpListener, TcpStream, UdpSocket };
+use std::sync::{ Arc, Mutex };
+use std::thread;
+use std::time::{ Duration, Instant };
+
+use crate::allocations::Allocations;
+use crate::attributes::error_code::ErrorCodeType;
+use crate::auth::Authenticator;
+use crate::channel_data;
+use crate::config::Config;
+use crate::discovery::Discovery;
+use crate::error::DecodeError;
+use crate::framing::{ self, TimedRead };
+use crate::handlers;
+use crate::header;
+use crate::message::Message;
+use crate::pool::WorkerPool;
+use crate::tls::{ self, DatagramChannel, DatagramQueue, DtlsAcceptor, Pollable, TlsStream };
+use crate::transport::{ self, Connection };
+
+use openssl::ssl::{ HandshakeError, MidHandshakeSslStream, SslAcceptor };
+use socket2::{ Domain, Protocol, Socket, Type };
+
+// Datagrams queued for a DTLS session beyond which more are dropped
+const DTLS_BACKLOG: usize = 64;
+// Clients yet to echo a DTLS cookie beyond which the longest waiting is given up on
+const DTLS_PENDING: usize = 1024;
+
+// DTLS sessions on a UDP socket by client, each with an ID so that a session ending
+// can't remove the one that replaced it
+type DtlsSessions = Arc<Mutex<HashMap<SocketAddr, (u64, Arc<DatagramQueue>)>>>;
+// DTLS handshakes waiting on a client to echo its cookie, with when each began
+type PendingHandshakes = HashMap<SocketAddr, (MidHandshakeSslStream<DatagramChannel>, Arc<DatagramQueue>, Instant)>;
+
+// State shared by every listener
+pub struct Server {
+    pub authenticator: Authenticator,
+    pub allocations: Allocations,
+    // Only set when an alternate address is configured
+    pub discovery: Option<Discovery>,
+}
+
+// Runs the server until every listener has stopped; fails if any of them cannot be set up
+pub fn run(config: Config) -> Result<(), String> {
+    // NAT behaviour discovery takes over the first listener's UDP port
+    let discovery = match (config.alternate, config.listeners[0].udp_port) {
+        (Some(alternate), Some(port)) => match Discovery::bind(SocketAddr::new(config.listeners[0].ip, port), alternate, config.listeners[0].v6_only) {
+            Ok(discovery) => Some(discovery),
+            Err(e) => return Err(format!("unable to establish udp sockets for nat discovery: {}", e)),
+        },
+        _ => None,
+    };
+
+    // The certificate is loaded up front too, and only if something will use it
+    let acceptor = match (&config.tls_certificate, &config.tls_private_key) {
+        (Some(certificate), Some(private_key)) if config.listeners.iter().any(|listener| listener.tls_port.is_some()) => Some(tls::acceptor(certificate, private_key)?),
+        _ => None,
+    };
+    let dtls_acceptor = match (&config.tls_certificate, &config.tls_private_key) {
+        (Some(certificate), Some(private_key)) if config.listeners.iter().any(|listener| listener.dtls_port.is_some()) => Some(tls::dtls_acceptor(certificate, private_key)?),
+        _ => None,
+    };
+
+    // Every socket is bound before any is served, so a bad address stops the server outright
+    let mut udp_sockets = match &discovery {
+        Some(discovery) => discovery.sockets(),
+        None => vec![],
+    };
+    let mut stream_listeners = vec![];
+    for (i, listener) in config.listeners.iter().enumerate() {
+        if let Some(port) = listener.udp_port {
+            let address = SocketAddr::new(listener.ip, port);
+            if i > 0 || discovery.is_none() {
+                match transport::bind_udp(address, listener.v6_only) {
+                    Ok(udp_socket) => udp_sockets.push(Arc::new(udp_socket)),
+                    Err(e) => return Err(format!("unable to establish udp socket at {}: {}", address, e)),
+                }
+            }
+        }
+        let tcp_listener = match listener.tcp_port {
+            Some(port) => match bind_tcp(SocketAddr::new(listener.ip, port), listener.v6_only) {
+                Ok(tcp_listener) => Some(tcp_listener),
+                Err(e) => return Err(format!("unable to establish tcp listener at {}: {}", SocketAddr::new(listener.ip, port), e)),
+            },
+            None => None,
+        };
+        let tls_listener = match (listener.tls_port, &acceptor) {
+            (Some(port), Some(acceptor)) => match bind_tcp(SocketAddr::new(listener.ip, port), listener.v6_only) {
+                Ok(tls_listener) => Some((tls_listener, acceptor.clone())),
+                Err(e) => return Err(format!("unable to establish tls listener at {}: {}", SocketAddr::new(listener.ip, port), e)),
+            },
+            _ => None,
+        };
+        let dtls_socket = match (listener.dtls_port, &dtls_acceptor) {
+            (Some(port), Some(acceptor)) => match transport::bind_udp(SocketAddr::new(listener.ip, port), listener.v6_only) {
+                Ok(dtls_socket) => Some((Arc::new(dtls_socket), acceptor.clone())),
+                Err(e) => return Err(format!("unable to establish dtls socket at {}: {}", SocketAddr::new(listener.ip, port), e)),
+            },
+            _ => None,
+        };
+        if tcp_listener.is_some() || tls_listener.is_some() || dtls_socket.is_some() {
+            stream_listeners.push((tcp_listener, tls_listener, dtls_socket, listener));
+        }
+    }
+
+    let server = Arc::new(Server {
+        authenticator: Authenticator::new(config.credentials, config.realm, config.users, config.shared_secrets),
+        allocations: Allocations::new(config.relay_ip, config.denied_peers, config.user_quota, config.total_quota),
+        discovery,
+    });
+
+    // Spawn a thread for each UDP socket
+    let mut listener_threads = vec![];
+    for udp_socket in udp_sockets {
+        let udp_server = Arc::clone(&server);
+        listener_threads.push(thread::spawn(move || serve_udp(udp_socket, udp_server)));
+    }
+
+    // Spawn thread to clean up expired allocations
+    let expiry_server = Arc::clone(&server);
+    thread::spawn(move || {
+        loop {
+            thread::sleep(Duration::from_secs(1));
+            expiry_server.allocations.expire();
+        }
+    });
+
+    // Spawn a thread to accept connections for each TCP, TLS and DTLS listener, with those
+    // on the same address sharing its workers
+    for (tcp_listener, tls_listener, dtls_socket, listener) in stream_listeners {
+        let pool = Arc::new(WorkerPool::new(listener.tcp_workers));
+        let idle_timeout = Duration::from_secs(listener.tcp_idle_timeout);
+        let read_timeout = Duration::from_secs(listener.tcp_read_timeout);
+        if let Some(tcp_listener) = tcp_listener {
+            let tcp_server = Arc::clone(&server);
+            let tcp_pool = Arc::clone(&pool);
+            listener_threads.push(thread::spawn(move || accept_tcp(tcp_listener, tcp_server, tcp_pool, idle_timeout, read_timeout)));
+        }
+        if let Some((tls_listener, acceptor)) = tls_listener {
+            let tls_server = Arc::clone(&server);
+            let tls_pool = Arc::clone(&pool);
+            listener_threads.push(thread::spawn(move || accept_tls(tls_listener, acceptor, tls_server, tls_pool, idle_timeout, read_timeout)));
+        }
+        if let Some((dtls_socket, acceptor)) = dtls_socket {
+            let dtls_server = Arc::clone(&server);
+            listener_threads.push(thread::spawn(move || accept_dtls(dtls_socket, acceptor, dtls_server, pool, idle_timeout, read_timeout)));
+        }
+    }
+
+    for listener_thread in listener_threads {
+        let _ = listener_thread.join();
+    }
+    Ok(())
+}
+
+fn bind_tcp(address: SocketAddr, v6_only: bool) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(address), Type::STREAM, Some(Protocol::TCP))?;
+    if address.is_ipv6() {
+        socket.set_only_v6(v6_only)?;
+    }
+    // Lets the server restart while connections from its last run are in TIME_WAIT
+    socket.set_reuse_address(true)?;
+    socket.bind(&address.into())?;
+    socket.listen(1024)?;
+    Ok(socket.into())
+}
+
+// Each connection is served by a worker for as long as it lasts
+fn accept_tcp(tcp_listener: TcpListener, server: Arc<Server>, pool: Arc<WorkerPool>, idle_timeout: Duration, read_timeout: Duration) {
+    for stream in tcp_listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        let tcp_server = Arc::clone(&server);
+        // Dropping the job closes the connection when every worker is busy
+        let _ = pool.execute(move || serve_tcp(stream, tcp_server, idle_timeout, read_timeout));
+    }
+}
+
+// As accept_tcp, with the handshake done by the worker so a slow client holds up nobody else
+fn accept_tls(tls_listener: TcpListener, acceptor: SslAcceptor, server: Arc<Server>, pool: Arc<WorkerPool>, idle_timeout: Duration, read_timeout: Duration) {
+    for stream in tls_listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        let tls_server = Arc::clone(&server);
+        let acceptor = acceptor.clone();
+        let _ = pool.execute(move || serve_tls(stream, acceptor, tls_server, idle_timeout, read_timeout));
+    }
+}
+
+// Hands each datagram to the session for the client it came from. A client that opens with a
+// DTLS handshake is sent a cookie, and given a session on a worker once it echoes it back.
+fn accept_dtls(socket: Arc<UdpSocket>, acceptor: DtlsAcceptor, server: Arc<Server>, pool: Arc<WorkerPool>, idle_timeout: Duration, read_timeout: Duration) {
+    let local_addr = match socket.local_addr() {
+        Ok(local_addr) => local_addr,
+        Err(_) => return,
+    };
+    let sessions: DtlsSessions = Arc::new(Mutex::new(HashMap::new()));
+    let mut pending: PendingHandshakes = HashMap::new();
+    let mut next_id = 0;
+    let mut buf = vec![0; 65536];
+    loop {
+        let (amt, src) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(_) => continue,
+        };
+        let mut sessions_guard = match sessions.lock() {
+            Ok(sessions) => sessions,
+            Err(_) => return,
+        };
+        let datagram = buf[0..amt].to_vec();
+        let datagram = match sessions_guard.get(&src) {
+            Some((_, queue)) => match queue.push(datagram) {
+                // The session has ended, so this may be the client starting another
+                Err(datagram) => datagram,
+                Ok(()) => continue,
+            },
+            None => datagram,
+        };
+        sessions_guard.remove(&src);
+        drop(sessions_guard);
+
+        let (handshake, queue, started) = match pending.remove(&src) {
+            Some((handshake, queue, started)) if started.elapsed() < read_timeout => {
+                let _ = queue.push(datagram);
+                (handshake.handshake(), queue, started)
+            },
+            _ => {
+                // Only a handshake record can start a session
+                if datagram.first() != Some(&22) {
+                    continue;
+                }
+                // Spoofed ClientHellos can fill this up, so rather than turning new clients away the
+                // oldest is dropped, which a real client will long since have answered
+                pending.retain(|_, (_, _, started)| started.elapsed() < read_timeout);
+                if pending.len() >= DTLS_PENDING {
+                    let oldest = pending.iter().min_by_key(|(_, (_, _, started))| *started).map(|(client, _)| *client);
+                    if let Some(oldest) = oldest {
+                        pending.remove(&oldest);
+                    }
+                }
+                let queue = Arc::new(DatagramQueue::new(DTLS_BACKLOG));
+                let _ = queue.push(datagram);
+                let mut channel = DatagramChannel::new(Arc::clone(&socket), src, Arc::clone(&queue));
+                let ssl = match acceptor.ssl(src) {
+                    Ok(ssl) => ssl,
+                    Err(_) => continue,
+                };
+                // Done here, a handshake can only get as far as waiting for the next datagram
+                if channel.set_nonblocking(true).is_err() {
+                    continue;
+                }
+                (ssl.accept(channel), queue, Instant::now())
+            },
+        };
+        let handshake = match handshake {
+            Err(HandshakeError::WouldBlock(handshake)) => handshake,
+            _ => continue,
+        };
+        if !acceptor.verified(handshake.ssl()) {
+            pending.insert(src, (handshake, queue, started));
+            continue;
+        }
+
+        let id = next_id;
+        next_id += 1;
+        match sessions.lock() {
+            Ok(mut sessions) => sessions.insert(src, (id, queue)),
+            Err(_) => return,
+        };
+        let dtls_server = Arc::clone(&server);
+        let dtls_sessions = Arc::clone(&sessions);
+        let job = move || {
+            serve_dtls(handshake, dtls_server, local_addr, idle_timeout, read_timeout);
+            if let Ok(mut sessions) = dtls_sessions.lock() {
+                if matches!(sessions.get(&src), Some((session_id, _)) if *session_id == id) {
+                    sessions.remove(&src);
+                }
+            }
+        };
+        if pool.execute(job).is_err() {
+            if let Ok(mut sessions) = sessions.lock() {
+                sessions.remove(&src);
+            }
+        }
+    }
+}
+
+fn serve_udp(socket: Arc<UdpSocket>, server: Arc<Server>) {
+    let local_addr = match socket.local_addr() {
+        Ok(local_addr) => local_addr,
+        Err(_) => return,
+    };
+    // Large enough for any datagram, since TURN Send indications carry application data
+    let mut buf = vec![0; 65536];
+    loop {
+        if let Ok((amt, src)) = socket.recv_from(&mut buf) {
+            let connection = Connection::udp(Arc::clone(&socket), src, local_addr);
+            if let Some(res) = process_message(&buf[0..amt], &connection, &server) {
+                let _ = connection.send(&res);
+            }
+        }
+    }
+}
+
+// Processes messages until the client closes the connection, which also ends its allocation,
+// or the connection goes idle without one. A connection that a ConnectionBind succeeds on
+// carries raw peer data from then on.
+fn serve_tcp(stream: TcpStream, server: Arc<Server>, idle_timeout: Duration, read_timeout: Duration) {
+    let (peer_addr, local_addr) = match (stream.peer_addr(), stream.local_addr()) {
+        (Ok(peer_addr), Ok(local_addr)) => (peer_addr, local_addr),
+        _ => return,
+    };
+    let reader = match stream.try_clone() {
+        Ok(reader) => reader,
+        Err(_) => return,
+    };
+    let connection = Connection::tcp(Arc::new(Mutex::new(stream)), peer_addr, local_addr);
+
+    serve_stream(reader, &connection, &server, |reader| framing::read_message_timeout(reader, idle_timeout, read_timeout), |reader| server.allocations.relay_connection(&connection.tuple, reader));
+}
+
+// As serve_tcp, over TLS
+fn serve_tls(stream: TcpStream, acceptor: SslAcceptor, server: Arc<Server>, idle_timeout: Duration, read_timeout: Duration) {
+    let (peer_addr, local_addr) = match (stream.peer_addr(), stream.local_addr()) {
+        (Ok(peer_addr), Ok(local_addr)) => (peer_addr, local_addr),
+        _ => return,
+    };
+    // A client that stalls in the handshake is treated like one that stalls in a message
+    if stream.set_read_timeout(Some(read_timeout)).is_err() {
+        return;
+    }
+    let stream = match acceptor.accept(stream) {
+        Ok(stream) => stream,
+        Err(_) => return,
+    };
+    let stream = match tls::shared(stream) {
+        Ok(stream) => stream,
+        Err(_) => return,
+    };
+    let connection = Connection::tls(Arc::clone(&stream), peer_addr, local_addr);
+
+    serve_stream(TlsStream::new(stream), &connection, &server, |reader| framing::read_message_timeout(reader, idle_timeout, read_timeout), |stream| server.allocations.relay_connection(&connection.tuple, stream));
+}
+
+// As serve_tls, over a channel fed with the client's datagrams, once the client has echoed its
+// cookie. The session ends when the client closes it, or it goes idle without an allocation.
+// Peer data is never relayed over DTLS.
+fn serve_dtls(mut handshake: MidHandshakeSslStream<DatagramChannel>, server: Arc<Server>, local_addr: SocketAddr, idle_timeout: Duration, read_timeout: Duration) {
+    let client = handshake.get_ref().client();
+    // A client that stalls in the rest of the handshake is treated like one that stalls in a message
+    if handshake.get_mut().set_nonblocking(false).is_err() || handshake.get_mut().set_read_timeout(Some(read_timeout)).is_err() {
+        return;
+    }
+    let stream = match handshake.handshake() {
+        Ok(stream) => stream,
+        Err(_) => return,
+    };
+    let stream = match tls::shared(stream) {
+        Ok(stream) => stream,
+        Err(_) => return,
+    };
+    let connection = Connection::dtls(Arc::clone(&stream), client, local_addr);
+
+    serve_stream(TlsStream::new(Arc::clone(&stream)), &connection, &server, |reader| framing::read_datagram_timeout(reader, idle_timeout), |_| false);
+
+    // Nothing else tells the client the session is over
+    stream.shutdown();
+}
+
+// Reads messages off a stream with read_message and answers them, until it closes, fails, goes
+// idle without an allocation, or after_message says it has been taken over
+fn serve_stream<R, F: FnMut(&mut R) -> io::Result<Option<Vec<u8>>>, G: Fn(&R) -> bool>(mut reader: R, connection: &Connection, server: &Server, mut read_message: F, after_message: G) {
+    loop {
+        let message = match read_message(&mut reader) {
+            Ok(Some(message)) => message,
+            // Allocations are kept alive by Refresh requests, which can be far apart
+            Ok(None) if server.allocations.has_allocation(&connection.tuple) => continue,
+            Ok(None) | Err(_) => break,
+        };
+        if let Some(res) = process_message(&message, connection, server) {
+            if connection.send(&res).is_err() {
+                break;
+            }
+        }
+        if after_message(&reader) {
+            break;
+        }
+    }
+
+    server.allocations.release(&connection.tuple);
+}
+
+fn process_message(message: &[u8], connection: &Connection, server: &Server) -> Option<Vec<u8>> {
+    // ChannelData never gets a response
+    if channel_data::is_channel_data(message) {
+        if let Some((channel, data)) = channel_data::parse(message) {
+            server.allocations.send_channel_data(&connection.tuple, channel, data);
+        }
+        return None;
+    }
+
+    if message.len() < 20 {
+        return None;
+    }
+    // The header alone says who to answer if the rest of the message is bad
+    let header = match header::verify_header(&message[0..20]) {
+        Ok(header) => header,
+        Err(DecodeError::UnknownMethod(method)) => return handlers::unknown_method_response(message, method),
+        Err(_) => return None,
+    };
+    let message = match Message::decode(message) {
+        Ok(message) => message,
+        Err(_) => return handlers::error_response(&header, ErrorCodeType::BadRequest, vec![], None),
+    };
+    let attributes = match message.decode_attributes() {
+        Ok(attributes) => attributes,
+        Err(_) => return handlers::error_response(&header, ErrorCodeType::BadRequest, vec![], None),
+    };
+
+    handlers::process_message(message.raw(), &message.header, &attributes, message.unknown_attributes(), connection, server)
+}
@@ -0,0 +1,444 @@
+extern crate hmac;
+extern crate libc;
+extern crate openssl;
+extern crate rand;
+extern crate sha1;
+
+use std::collections::VecDeque;
+use std::io::{ self, ErrorKind, Read, Write };
+use std::net::{ Shutdown, SocketAddr, TcpStream, UdpSocket };
+use std::os::unix::io::{ AsRawFd, RawFd };
+use std::path::Path;
+use std::sync::{ Arc, Condvar, Mutex, MutexGuard };
+use std::time::{ Duration, Instant };
+
+use hmac::{ Hmac, Mac };
+use openssl::error::ErrorStack;
+use openssl::ex_data::Index;
+use openssl::ssl::{ Ssl, SslAcceptor, SslAcceptorBuilder, SslFiletype, SslMethod, SslOptions, SslRef, SslStream };
+use sha1::Sha1;
+
+use crate::allocations::DataConnection;
+use crate::framing::TimedRead;
+
+// Largest datagram DTLS sends, leaving room for IP and UDP headers on most paths
+const DTLS_MTU: u32 = 1200;
+
+// Loads the certificate chain and private key from PEM files
+pub fn acceptor(certificate:&Path, private_key:&Path) -> Result<SslAcceptor, String> {
+    match SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()) {
+        Ok(builder) => load(builder, certificate, private_key),
+        Err(e) => Err(format!("unable to set up tls: {}", e)),
+    }
+}
+
+// As acceptor, for DTLS. The v5 profile pins TLS versions, which a DTLS context rejects.
+pub fn dtls_acceptor(certificate:&Path, private_key:&Path) -> Result<DtlsAcceptor, String> {
+    let (mut builder, peer) = match (SslAcceptor::mozilla_intermediate(SslMethod::dtls()), Ssl::new_ex_index::<Peer>()) {
+        (Ok(builder), Ok(peer)) => (builder, peer),
+        (Err(e), _) | (_, Err(e)) => return Err(format!("unable to set up dtls: {}", e)),
+    };
+
+    // Cookies are a MAC of the client's address, so none need to be kept
+    let secret: [u8; 20] = rand::random();
+    builder.set_options(SslOptions::COOKIE_EXCHANGE);
+    builder.set_cookie_generate_cb(move |ssl, cookie| {
+        let mac = match ssl.ex_data(peer).and_then(|peer| cookie_mac(&secret, &peer.address)) {
+            Some(mac) => mac.finalize().into_bytes(),
+            None => return Err(ErrorStack::get()),
+        };
+        cookie[0..mac.len()].copy_from_slice(&mac);
+        Ok(mac.len())
+    });
+    builder.set_cookie_verify_cb(move |ssl, cookie| {
+        let valid = match ssl.ex_data(peer).and_then(|peer| cookie_mac(&secret, &peer.address)) {
+            Some(mac) => mac.verify_slice(cookie).is_ok(),
+            None => false,
+        };
+        if let (true, Some(peer)) = (valid, ssl.ex_data_mut(peer)) {
+            peer.verified = true;
+        }
+        valid
+    });
+
+    Ok(DtlsAcceptor {
+        acceptor: load(builder, certificate, private_key)?,
+        peer,
+    })
+}
+
+fn cookie_mac(secret:&[u8], address:&SocketAddr) -> Option<Hmac<Sha1>> {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).ok()?;
+    mac.update(address.to_string().as_bytes());
+    Some(mac)
+}
+
+fn load(mut builder:SslAcceptorBuilder, certificate:&Path, private_key:&Path) -> Result<SslAcceptor, String> {
+    if let Err(e) = builder.set_certificate_chain_file(certificate) {
+        return Err(format!("unable to load tls_certificate {}: {}", certificate.display(), e));
+    }
+    if let Err(e) = builder.set_private_key_file(private_key, SslFiletype::PEM) {
+        return Err(format!("unable to load tls_private_key {}: {}", private_key.display(), e));
+    }
+    if let Err(e) = builder.check_private_key() {
+        return Err(format!("tls_private_key does not match tls_certificate: {}", e));
+    }
+    Ok(builder.build())
+}
+
+// The client a DTLS handshake is with
+struct Peer {
+    address: SocketAddr,
+    // Set once the client has echoed the cookie sent to it
+    verified: bool,
+}
+
+// Accepts DTLS sessions with a cookie exchange (RFC 6347 section 4.2.1), so that nothing
+// more than a HelloVerifyRequest is sent to an address before the client shows it can
+// receive there
+#[derive(Clone)]
+pub struct DtlsAcceptor {
+    acceptor: SslAcceptor,
+    peer: Index<Ssl, Peer>,
+}
+impl DtlsAcceptor {
+    pub fn ssl(&self, client:SocketAddr) -> Result<Ssl, ErrorStack> {
+        let mut ssl = Ssl::new(self.acceptor.context())?;
+        ssl.set_mtu(DTLS_MTU)?;
+        ssl.set_ex_data(self.peer, Peer {
+            address: client,
+            verified: false,
+        });
+        Ok(ssl)
+    }
+
+    // Whether the client has echoed its cookie, and so the handshake can go on
+    pub fn verified(&self, ssl:&SslRef) -> bool {
+        ssl.ex_data(self.peer).is_some_and(|peer| peer.verified)
+    }
+}
+
+// Makes a stream that has completed its handshake ready to be shared between a TlsStream
+// and writers. The transport is made non-blocking, so that neither holds the lock while
+// waiting on the network.
+pub fn shared<S: Pollable>(mut stream:SslStream<S>) -> io::Result<Arc<SharedStream<S>>> {
+    let readiness = stream.get_ref().readiness()?;
+    stream.get_mut().set_nonblocking(true)?;
+    Ok(Arc::new(SharedStream {
+        stream: Mutex::new(stream),
+        readiness,
+    }))
+}
+
+// A transport TLS can run over without blocking
+pub trait Pollable: Read + Write {
+    fn set_nonblocking(&mut self, nonblocking:bool) -> io::Result<()>;
+    // Something to wait on for the transport, usable without holding it
+    fn readiness(&self) -> io::Result<Box<dyn Readiness>>;
+}
+impl Pollable for TcpStream {
+    fn set_nonblocking(&mut self, nonblocking:bool) -> io::Result<()> {
+        TcpStream::set_nonblocking(self, nonblocking)
+    }
+
+    fn readiness(&self) -> io::Result<Box<dyn Readiness>> {
+        Ok(Box::new(SocketReadiness(self.try_clone()?)))
+    }
+}
+
+pub trait Readiness: Send + Sync {
+    // False if the timeout passed first
+    fn wait_readable(&self, timeout:Option<Duration>) -> io::Result<bool>;
+    fn wait_writable(&self) -> io::Result<()>;
+}
+
+struct SocketReadiness(TcpStream);
+impl Readiness for SocketReadiness {
+    fn wait_readable(&self, timeout:Option<Duration>) -> io::Result<bool> {
+        poll(self.0.as_raw_fd(), libc::POLLIN, timeout)
+    }
+
+    fn wait_writable(&self) -> io::Result<()> {
+        poll(self.0.as_raw_fd(), libc::POLLOUT, None).map(|_| ())
+    }
+}
+
+fn poll(fd:RawFd, events:libc::c_short, timeout:Option<Duration>) -> io::Result<bool> {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events,
+        revents: 0,
+    };
+    // Rounded up, so that a wait for less than a millisecond doesn't spin
+    let timeout = match timeout {
+        Some(timeout) => timeout.as_micros().div_ceil(1000).min(libc::c_int::MAX as u128) as libc::c_int,
+        None => -1,
+    };
+    loop {
+        // Safe as pollfd outlives the call, which is told it is the only one
+        match unsafe { libc::poll(&mut pollfd, 1, timeout) } {
+            -1 => {
+                let e = io::Error::last_os_error();
+                if e.kind() != ErrorKind::Interrupted {
+                    return Err(e);
+                }
+            },
+            0 => return Ok(false),
+            // Errors and hang-ups count too, for the read or write to report them
+            _ => return Ok(true),
+        }
+    }
+}
+
+// A TLS stream shared between the thread reading from it and those writing to it. A TLS
+// stream can't be split in two like a TCP one, so each takes the lock only to move what is
+// ready, and waits for more without it.
+pub struct SharedStream<S> {
+    stream: Mutex<SslStream<S>>,
+    readiness: Box<dyn Readiness>,
+}
+impl<S: Read + Write> SharedStream<S> {
+    pub fn write(&self, buf:&[u8]) -> io::Result<usize> {
+        loop {
+            match self.lock()?.write(buf) {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => self.readiness.wait_writable()?,
+                result => return result,
+            }
+        }
+    }
+
+    pub fn write_all(&self, mut buf:&[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.write(buf)? {
+                0 => return Err(io::Error::new(ErrorKind::WriteZero, "failed to write whole buffer")),
+                amt => buf = &buf[amt..],
+            }
+        }
+        Ok(())
+    }
+
+    // Tells the client that nothing more will be sent
+    pub fn shutdown(&self) {
+        if let Ok(mut stream) = self.lock() {
+            let _ = stream.shutdown();
+        }
+    }
+
+    fn lock(&self) -> io::Result<MutexGuard<'_, SslStream<S>>> {
+        self.stream.lock().map_err(|_| io::Error::other("stream lock poisoned"))
+    }
+}
+impl SharedStream<TcpStream> {
+    // As shutdown, then closes the connection itself, which wakes anything waiting on it
+    pub fn close(&self) {
+        if let Ok(mut stream) = self.lock() {
+            let _ = stream.shutdown();
+            let _ = stream.get_ref().shutdown(Shutdown::Both);
+        }
+    }
+}
+
+// Reads from and writes to a SharedStream, with a read timeout of its own
+pub struct TlsStream<S> {
+    stream: Arc<SharedStream<S>>,
+    timeout: Option<Duration>,
+}
+impl<S> TlsStream<S> {
+    pub fn new(stream:Arc<SharedStream<S>>) -> TlsStream<S> {
+        TlsStream {
+            stream,
+            timeout: None,
+        }
+    }
+}
+impl<S: Read + Write> Read for TlsStream<S> {
+    fn read(&mut self, buf:&mut [u8]) -> io::Result<usize> {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            // A record read in part is kept by the stream until the rest arrives
+            match self.stream.lock()?.read(buf) {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => (),
+                result => return result,
+            }
+            let remaining = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(remaining) => Some(remaining),
+                    None => return Err(io::Error::new(ErrorKind::TimedOut, "read timed out")),
+                },
+                None => None,
+            };
+            self.stream.readiness.wait_readable(remaining)?;
+        }
+    }
+}
+impl<S: Read + Write> Write for TlsStream<S> {
+    fn write(&mut self, buf:&[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+impl<S: Read + Write> TimedRead for TlsStream<S> {
+    fn set_read_timeout(&mut self, timeout:Option<Duration>) -> io::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+}
+impl DataConnection for TlsStream<TcpStream> {
+    fn try_clone(&self) -> io::Result<TlsStream<TcpStream>> {
+        Ok(TlsStream::new(Arc::clone(&self.stream)))
+    }
+
+    fn close(&self) {
+        self.stream.close();
+    }
+}
+
+// Datagrams from one client, handed over by whatever reads the socket they arrive on
+pub struct DatagramQueue {
+    state: Mutex<QueueState>,
+    arrived: Condvar,
+    capacity: usize,
+}
+struct QueueState {
+    datagrams: VecDeque<Vec<u8>>,
+    closed: bool,
+}
+impl DatagramQueue {
+    pub fn new(capacity:usize) -> DatagramQueue {
+        DatagramQueue {
+            state: Mutex::new(QueueState {
+                datagrams: VecDeque::new(),
+                closed: false,
+            }),
+            arrived: Condvar::new(),
+            capacity,
+        }
+    }
+
+    // Gives the datagram back once the channel reading the queue is gone. A queue that
+    // has fallen behind drops it, as the network would.
+    pub fn push(&self, datagram:Vec<u8>) -> Result<(), Vec<u8>> {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return Err(datagram),
+        };
+        if state.closed {
+            return Err(datagram);
+        }
+        if state.datagrams.len() < self.capacity {
+            state.datagrams.push_back(datagram);
+            self.arrived.notify_all();
+        }
+        Ok(())
+    }
+
+    fn close(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.closed = true;
+        }
+    }
+
+    // Waits up to the timeout for a datagram, without taking it. False if none arrived.
+    fn wait(&self, timeout:Option<Duration>) -> io::Result<bool> {
+        let state = self.state.lock().map_err(|_| io::Error::other("queue lock poisoned"))?;
+        let waiting = |state:&mut QueueState| state.datagrams.is_empty();
+        let state = match timeout {
+            Some(timeout) => self.arrived.wait_timeout_while(state, timeout, waiting).ok().map(|(state, _)| state),
+            None => self.arrived.wait_while(state, waiting).ok(),
+        };
+        match state {
+            Some(state) => Ok(!state.datagrams.is_empty()),
+            None => Err(io::Error::other("queue lock poisoned")),
+        }
+    }
+
+    fn pop(&self) -> io::Result<Option<Vec<u8>>> {
+        match self.state.lock() {
+            Ok(mut state) => Ok(state.datagrams.pop_front()),
+            Err(_) => Err(io::Error::other("queue lock poisoned")),
+        }
+    }
+}
+impl Readiness for Arc<DatagramQueue> {
+    fn wait_readable(&self, timeout:Option<Duration>) -> io::Result<bool> {
+        self.wait(timeout)
+    }
+
+    // Datagrams are sent straight out on the socket
+    fn wait_writable(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// One client's share of a UDP socket, for DTLS to run over. Datagrams from the client are
+// read off a queue, and datagrams to it are sent straight out.
+pub struct DatagramChannel {
+    socket: Arc<UdpSocket>,
+    client: SocketAddr,
+    queue: Arc<DatagramQueue>,
+    timeout: Option<Duration>,
+    nonblocking: bool,
+}
+impl DatagramChannel {
+    pub fn new(socket:Arc<UdpSocket>, client:SocketAddr, queue:Arc<DatagramQueue>) -> DatagramChannel {
+        DatagramChannel {
+            socket,
+            client,
+            queue,
+            timeout: None,
+            nonblocking: false,
+        }
+    }
+
+    pub fn client(&self) -> SocketAddr {
+        self.client
+    }
+}
+impl Read for DatagramChannel {
+    // Like a UDP socket, reads a whole datagram, dropping whatever does not fit
+    fn read(&mut self, buf:&mut [u8]) -> io::Result<usize> {
+        if !self.nonblocking && !self.queue.wait(self.timeout)? {
+            return Err(io::Error::new(ErrorKind::WouldBlock, "no datagram"));
+        }
+        let datagram = match self.queue.pop()? {
+            Some(datagram) => datagram,
+            None => return Err(io::Error::new(ErrorKind::WouldBlock, "no datagram")),
+        };
+        let amt = datagram.len().min(buf.len());
+        buf[0..amt].copy_from_slice(&datagram[0..amt]);
+        Ok(amt)
+    }
+}
+impl Write for DatagramChannel {
+    fn write(&mut self, buf:&[u8]) -> io::Result<usize> {
+        self.socket.send_to(buf, self.client)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+impl TimedRead for DatagramChannel {
+    fn set_read_timeout(&mut self, timeout:Option<Duration>) -> io::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+}
+impl Pollable for DatagramChannel {
+    fn set_nonblocking(&mut self, nonblocking:bool) -> io::Result<()> {
+        self.nonblocking = nonblocking;
+        Ok(())
+    }
+
+    fn readiness(&self) -> io::Result<Box<dyn Readiness>> {
+        Ok(Box::new(Arc::clone(&self.queue)))
+    }
+}
+impl Drop for DatagramChannel {
+    fn drop(&mut self) {
+        self.queue.close();
+    }
+}
@@ -0,0 +1,118 @@
+extern crate socket2;
+
+use std::io::{ self, Write };
+use std::net::{ SocketAddr, TcpStream, UdpSocket };
+use std::sync::{ Arc, Mutex };
+
+use socket2::{ Domain, Protocol, Type };
+
+use crate::tls::{ DatagramChannel, SharedStream };
+
+#[derive(Copy, Clone, Hash, PartialEq, Eq)]
+pub enum Transport {
+    Udp,
+    Tcp,
+    // TCP with TLS on top (RFC 5389 section 7.2.2)
+    Tls,
+    // UDP with DTLS on top (RFC 7350)
+    Dtls,
+}
+
+// Identifies the client side of an allocation
+#[derive(Copy, Clone, Hash, PartialEq, Eq)]
+pub struct FiveTuple {
+    pub client: SocketAddr,
+    pub server: SocketAddr,
+    pub transport: Transport,
+}
+
+#[derive(Clone)]
+enum Socket {
+    Udp(Arc<UdpSocket>),
+    // Writes are serialised so relayed data cannot interleave with responses
+    Tcp(Arc<Mutex<TcpStream>>),
+    Tls(Arc<SharedStream<TcpStream>>),
+    Dtls(Arc<SharedStream<DatagramChannel>>),
+}
+
+// A client together with the socket its messages arrived on, so that traffic
+// can be sent back to it outside of a request/response exchange
+#[derive(Clone)]
+pub struct Connection {
+    pub tuple: FiveTuple,
+    socket: Socket,
+}
+impl Connection {
+    pub fn udp(socket:Arc<UdpSocket>, client:SocketAddr, server:SocketAddr) -> Connection {
+        Connection {
+            tuple: FiveTuple {
+                client,
+                server,
+                transport: Transport::Udp,
+            },
+            socket: Socket::Udp(socket),
+        }
+    }
+
+    pub fn tcp(stream:Arc<Mutex<TcpStream>>, client:SocketAddr, server:SocketAddr) -> Connection {
+        Connection {
+            tuple: FiveTuple {
+                client,
+                server,
+                transport: Transport::Tcp,
+            },
+            socket: Socket::Tcp(stream),
+        }
+    }
+
+    pub fn tls(stream:Arc<SharedStream<TcpStream>>, client:SocketAddr, server:SocketAddr) -> Connection {
+        Connection {
+            tuple: FiveTuple {
+                client,
+                server,
+                transport: Transport::Tls,
+            },
+            socket: Socket::Tls(stream),
+        }
+    }
+
+    pub fn dtls(stream:Arc<SharedStream<DatagramChannel>>, client:SocketAddr, server:SocketAddr) -> Connection {
+        Connection {
+            tuple: FiveTuple {
+                client,
+                server,
+                transport: Transport::Dtls,
+            },
+            socket: Socket::Dtls(stream),
+        }
+    }
+
+    // The client's address as it would be seen without a dual-stack socket in between,
+    // which reports IPv4 clients as IPv4-mapped IPv6 addresses
+    pub fn mapped_address(&self) -> SocketAddr {
+        SocketAddr::new(self.tuple.client.ip().to_canonical(), self.tuple.client.port())
+    }
+
+    pub fn send(&self, message:&[u8]) -> io::Result<()> {
+        match &self.socket {
+            Socket::Udp(socket) => socket.send_to(message, self.tuple.client).map(|_| ()),
+            Socket::Tcp(stream) => match stream.lock() {
+                Ok(mut stream) => stream.write_all(message),
+                Err(_) => Err(io::Error::other("stream lock poisoned")),
+            },
+            Socket::Tls(stream) => stream.write_all(message),
+            // Each write is a record of its own, and so a datagram of its own
+            Socket::Dtls(stream) => stream.write_all(message),
+        }
+    }
+}
+
+// Binds a UDP socket, accepting IPv4 clients too on an IPv6 wildcard address unless v6_only
+pub fn bind_udp(address: SocketAddr, v6_only: bool) -> io::Result<UdpSocket> {
+    let socket = socket2::Socket::new(Domain::for_address(address), Type::DGRAM, Some(Protocol::UDP))?;
+    if address.is_ipv6() {
+        socket.set_only_v6(v6_only)?;
+    }
+    socket.bind(&address.into())?;
+    Ok(socket.into())
+}
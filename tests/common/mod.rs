@@ -0,0 +1,154 @@
+// Runs the server binary, and makes up the random input fed to it and the decoders. Without
+// the server feature there is no binary to run.
+#![allow(dead_code)]
+#![cfg_attr(not(feature = "server"), allow(unused_imports))]
+
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::net::UdpSocket;
+use std::path::PathBuf;
+use std::process::{ self, Child, Command, Stdio };
+
+use openssl::asn1::Asn1Time;
+use openssl::ec::{ EcGroup, EcKey };
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::PKey;
+use openssl::x509::{ X509Builder, X509NameBuilder };
+use rand::Rng;
+use rand::rngs::StdRng;
+
+use stun::header::{ MessageClass, MessageHeader, MessageType };
+
+// Fixed, so that a failure can be reproduced
+pub const SEED: u64 = 5389;
+
+// A server running in a directory of its own, where it finds config.toml. Killed when dropped.
+#[cfg(feature = "server")]
+pub struct Server {
+    child: Child,
+    dir: PathBuf,
+}
+#[cfg(feature = "server")]
+impl Server {
+    pub fn start(name:&str, config:&str, files:&[(&str, Vec<u8>)]) -> Server {
+        let dir = env::temp_dir().join(format!("stun-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("config.toml"), config).unwrap();
+        for (file, contents) in files {
+            fs::write(dir.join(file), contents).unwrap();
+        }
+
+        let child = Command::new(env!("CARGO_BIN_EXE_stun"))
+            .current_dir(&dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+        Server {
+            child,
+            dir,
+        }
+    }
+
+    // Stops the server, giving back what it wrote to stderr, which is where panics go
+    pub fn stop(mut self) -> String {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let mut stderr = String::new();
+        if let Some(mut pipe) = self.child.stderr.take() {
+            let _ = pipe.read_to_string(&mut stderr);
+        }
+        stderr
+    }
+}
+#[cfg(feature = "server")]
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+// A port nothing is bound to at the moment, on any loopback address
+pub fn free_port() -> u16 {
+    UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+// A self-signed certificate and its private key, as PEM
+pub fn certificate() -> (Vec<u8>, Vec<u8>) {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", "localhost").unwrap();
+    let name = name.build();
+
+    let mut builder = X509Builder::new().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(&name).unwrap();
+    builder.set_pubkey(&key).unwrap();
+    builder.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+    builder.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+    builder.sign(&key, MessageDigest::sha256()).unwrap();
+    (builder.build().to_pem().unwrap(), key.private_key_to_pem_pkcs8().unwrap())
+}
+
+// Attribute types with a decoder of their own
+const TYPE_CODES: [u16; 23] = [
+    0x0001, 0x0003, 0x0006, 0x0008, 0x0009, 0x000A, 0x000C, 0x000D, 0x0012, 0x0013, 0x0014, 0x0015,
+    0x0016, 0x0019, 0x0020, 0x0024, 0x0025, 0x0026, 0x0027, 0x002A, 0x8028, 0x802B, 0x802C,
+];
+const MESSAGE_TYPES: [MessageType; 10] = [
+    MessageType::Binding,
+    MessageType::Allocate,
+    MessageType::Refresh,
+    MessageType::Send,
+    MessageType::Data,
+    MessageType::CreatePermission,
+    MessageType::ChannelBind,
+    MessageType::Connect,
+    MessageType::ConnectionBind,
+    MessageType::ConnectionAttempt,
+];
+const MESSAGE_CLASSES: [MessageClass; 4] = [MessageClass::Request, MessageClass::Indication, MessageClass::Success, MessageClass::Error];
+
+pub fn random_bytes(rng:&mut StdRng, max:usize) -> Vec<u8> {
+    let length = rng.gen_range(0..=max);
+    (0..length).map(|_| rng.gen()).collect()
+}
+
+pub fn random_header(rng:&mut StdRng, length:u16) -> MessageHeader {
+    MessageHeader {
+        mtype: MESSAGE_TYPES[rng.gen_range(0..MESSAGE_TYPES.len())],
+        mclass: MESSAGE_CLASSES[rng.gen_range(0..MESSAGE_CLASSES.len())],
+        length,
+        id: rng.gen(),
+    }
+}
+
+// A message that gets past the header checks, holding attributes of known types with random
+// values. Attribute lengths are usually right, so that decoding gets as far as the values.
+pub fn random_message(rng:&mut StdRng) -> Vec<u8> {
+    let mut body = vec![];
+    for _ in 0..rng.gen_range(0..8) {
+        let type_code = match rng.gen_bool(0.9) {
+            true => TYPE_CODES[rng.gen_range(0..TYPE_CODES.len())],
+            false => rng.gen(),
+        };
+        let value = random_bytes(rng, 40);
+        let length = match rng.gen_bool(0.9) {
+            true => value.len() as u16,
+            false => rng.gen(),
+        };
+        body.extend_from_slice(&type_code.to_be_bytes());
+        body.extend_from_slice(&length.to_be_bytes());
+        body.extend_from_slice(&value);
+        body.resize(body.len().div_ceil(4) * 4, 0);
+    }
+    let mut message = random_header(rng, body.len() as u16).serialise();
+    message.append(&mut body);
+    message
+}
@@ -0,0 +1,143 @@
+// Decoders fed truncated and random input, which they must reject rather than panic on
+extern crate openssl;
+extern crate rand;
+extern crate stun;
+
+mod common;
+
+use rand::{ Rng, SeedableRng };
+use rand::rngs::StdRng;
+
+use stun::attributes::{ self, Attribute };
+use stun::attributes::change_request::ChangeRequest;
+use stun::attributes::channel_number::ChannelNumber;
+use stun::attributes::connection_id::ConnectionId;
+use stun::attributes::data::Data;
+use stun::attributes::error_code::{ ErrorCode, ErrorCodeType };
+use stun::attributes::fingerprint::Fingerprint;
+use stun::attributes::lifetime::Lifetime;
+use stun::attributes::mapped_address::MappedAddress;
+use stun::attributes::message_integrity::MessageIntegrity;
+use stun::attributes::nonce::Nonce;
+use stun::attributes::other_address::OtherAddress;
+use stun::attributes::padding::Padding;
+use stun::attributes::priority::Priority;
+use stun::attributes::realm::Realm;
+use stun::attributes::requested_transport::RequestedTransport;
+use stun::attributes::response_origin::ResponseOrigin;
+use stun::attributes::response_port::ResponsePort;
+use stun::attributes::unknown_attributes::UnknownAttributes;
+use stun::attributes::use_candidate::UseCandidate;
+use stun::attributes::username::Username;
+use stun::attributes::xor_mapped_address::XorMappedAddress;
+use stun::attributes::xor_peer_address::XorPeerAddress;
+use stun::attributes::xor_relayed_address::XorRelayedAddress;
+use stun::header::{ self, MessageHeader };
+use stun::message::Message;
+
+use common::{ random_bytes, random_header, random_message, SEED };
+
+const ROUNDS: usize = 10000;
+
+// Runs a message through every decoder, none of which may panic
+fn decode(message:&[u8]) {
+    if let Ok(header) = header::verify_header(&message[0..message.len().min(20)]) {
+        let _ = attributes::get_attributes(&message[20..], &header);
+    }
+    if let Ok(message) = Message::decode(message) {
+        let _ = message.decode_attributes();
+        let _ = message.unknown_attributes();
+    }
+}
+
+fn deserialise<T: Attribute>(value:&[u8], header:&MessageHeader) {
+    let _ = T::new().deserialise(value, header);
+}
+
+#[test]
+fn truncated_messages_are_rejected() {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    for _ in 0..ROUNDS / 10 {
+        let message = random_message(&mut rng);
+        for length in 0..message.len() {
+            let truncated = &message[0..length];
+            decode(truncated);
+            assert!(Message::decode(truncated).is_err());
+        }
+        decode(&message);
+    }
+}
+
+#[test]
+fn random_messages_do_not_panic() {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    for _ in 0..ROUNDS {
+        decode(&random_bytes(&mut rng, 100));
+
+        // Random bodies behind a valid header, with the length field right or not
+        let body = random_bytes(&mut rng, 100);
+        let length = match rng.gen_bool(0.5) {
+            true => body.len() as u16,
+            false => rng.gen(),
+        };
+        let mut message = random_header(&mut rng, length).serialise();
+        message.extend_from_slice(&body);
+        decode(&message);
+
+        let mut message = random_message(&mut rng);
+        let index = rng.gen_range(0..message.len());
+        message[index] = rng.gen();
+        decode(&message);
+    }
+}
+
+#[test]
+fn random_attribute_values_do_not_panic() {
+    let mut rng = StdRng::seed_from_u64(SEED);
+    for _ in 0..ROUNDS {
+        let value = random_bytes(&mut rng, 40);
+        let header = random_header(&mut rng, 0);
+        deserialise::<ChangeRequest>(&value, &header);
+        deserialise::<ChannelNumber>(&value, &header);
+        deserialise::<ConnectionId>(&value, &header);
+        deserialise::<Data>(&value, &header);
+        deserialise::<ErrorCode>(&value, &header);
+        deserialise::<Fingerprint>(&value, &header);
+        deserialise::<Lifetime>(&value, &header);
+        deserialise::<MappedAddress>(&value, &header);
+        deserialise::<MessageIntegrity>(&value, &header);
+        deserialise::<Nonce>(&value, &header);
+        deserialise::<OtherAddress>(&value, &header);
+        deserialise::<Padding>(&value, &header);
+        deserialise::<Priority>(&value, &header);
+        deserialise::<Realm>(&value, &header);
+        deserialise::<RequestedTransport>(&value, &header);
+        deserialise::<ResponseOrigin>(&value, &header);
+        deserialise::<ResponsePort>(&value, &header);
+        deserialise::<UnknownAttributes>(&value, &header);
+        deserialise::<UseCandidate>(&value, &header);
+        deserialise::<Username>(&value, &header);
+        deserialise::<XorMappedAddress>(&value, &header);
+        deserialise::<XorPeerAddress>(&value, &header);
+        deserialise::<XorRelayedAddress>(&value, &header);
+    }
+}
+
+#[test]
+fn error_codes_without_a_type_are_kept() {
+    let header = random_header(&mut StdRng::seed_from_u64(SEED), 0);
+    for (value, code) in [(&b"\x00\x00\x04\x57Role Conflict"[..], 487), (b"\x00\x00\x06\x63", 699), (b"\x00\x00\x04\x56Allocation Quota Reached", 486)] {
+        let mut error_code = ErrorCode::new();
+        assert!(error_code.deserialise(value, &header).is_ok());
+        assert_eq!(error_code.code(), Some(code));
+        assert_eq!(error_code.serialise().as_deref(), Some(value));
+    }
+    let mut error_code = ErrorCode::new();
+    assert!(error_code.deserialise(b"\x00\x00\x04\x57", &header).is_ok());
+    assert!(error_code.code_type() == Some(ErrorCodeType::Other(487)));
+
+    // Classes run from 3 to 6 and numbers up to 99
+    for value in [&b"\x00\x00\x02\x00"[..], b"\x00\x00\x07\x00", b"\x00\x00\x04\x64"] {
+        assert!(ErrorCode::new().deserialise(value, &header).is_err());
+    }
+}
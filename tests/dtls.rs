@@ -0,0 +1,106 @@
+// A DTLS client handshaking with the server after a flood of spoofed ClientHellos, none of
+// which will ever echo their cookie
+extern crate openssl;
+extern crate rand;
+extern crate stun;
+
+mod common;
+
+use std::io::{ self, Read, Write };
+use std::net::{ SocketAddr, UdpSocket };
+use std::time::Duration;
+
+use openssl::ssl::{ HandshakeError, Ssl, SslContext, SslMethod, SslVerifyMode };
+
+use stun::client;
+
+use common::{ certificate, free_port, Server };
+
+// More than the server keeps handshakes pending for
+const SPOOFED: usize = 1500;
+
+// A UDP socket connected to the server, as the stream a DTLS client runs over
+struct Datagrams(UdpSocket);
+impl Read for Datagrams {
+    fn read(&mut self, buf:&mut [u8]) -> io::Result<usize> {
+        self.0.recv(buf)
+    }
+}
+impl Write for Datagrams {
+    fn write(&mut self, buf:&[u8]) -> io::Result<usize> {
+        self.0.send(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// The server's certificate is self-signed
+fn client() -> Ssl {
+    let mut context = SslContext::builder(SslMethod::dtls()).unwrap();
+    context.set_verify(SslVerifyMode::NONE);
+    Ssl::new(&context.build()).unwrap()
+}
+
+// The first datagram of a handshake, which is all a spoofed client ever sends
+fn client_hello() -> Vec<u8> {
+    let sink = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.connect(sink.local_addr().unwrap()).unwrap();
+    socket.set_nonblocking(true).unwrap();
+    match client().connect(Datagrams(socket)) {
+        Err(HandshakeError::WouldBlock(_)) => (),
+        _ => panic!("handshake went further than the ClientHello"),
+    }
+    let mut buf = vec![0; 2048];
+    let amt = sink.recv(&mut buf).unwrap();
+    buf.truncate(amt);
+    buf
+}
+
+#[test]
+fn spoofed_client_hellos_do_not_lock_clients_out() {
+    let (port, dtls_port) = (free_port(), free_port());
+    let config = format!("tcp_read_timeout = 60\n\
+                          tls_certificate = \"cert.pem\"\n\
+                          tls_private_key = \"key.pem\"\n\
+                          [[listen]]\n\
+                          ip = \"127.0.0.1\"\n\
+                          udp_port = {}\n\
+                          dtls_port = {}\n", port, dtls_port);
+    let (certificate, private_key) = certificate();
+    let server = Server::start("dtls", &config, &[("cert.pem", certificate), ("key.pem", private_key)]);
+    let address = SocketAddr::from(([127, 0, 0, 1], port));
+    let dtls_address = SocketAddr::from(([127, 0, 0, 1], dtls_port));
+
+    // Retransmitted until the server is up
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client::binding_udp(&socket, address).unwrap();
+
+    // Each from a loopback address of its own, which is sent a HelloVerifyRequest and never
+    // heard from again
+    let hello = client_hello();
+    let mut buf = vec![0; 2048];
+    for spoofed in 0..SPOOFED {
+        let socket = UdpSocket::bind(SocketAddr::from(([127, 1, (spoofed / 250) as u8, (spoofed % 250 + 1) as u8], 0))).unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        socket.send_to(&hello, dtls_address).unwrap();
+        assert!(socket.recv(&mut buf).is_ok(), "no HelloVerifyRequest for ClientHello {}", spoofed);
+    }
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket.connect(dtls_address).unwrap();
+    socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut stream = match client().connect(Datagrams(socket)) {
+        Ok(stream) => stream,
+        Err(_) => panic!("no DTLS handshake"),
+    };
+    stream.write_all(&client::binding_request(&rand::random(), vec![])).unwrap();
+    let mut response = vec![0; 2048];
+    let amt = stream.read(&mut response).unwrap();
+    client::mapped_address(&response[0..amt]).unwrap();
+
+    let stderr = server.stop();
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
@@ -0,0 +1,116 @@
+// stun-natcheck run against the server on two loopback addresses, with no NAT in between
+extern crate openssl;
+extern crate rand;
+extern crate stun;
+
+mod common;
+
+use std::net::{ SocketAddr, UdpSocket };
+use std::process::Command;
+use std::time::Duration;
+
+use stun::attributes::change_request::ChangeRequest;
+use stun::attributes::AttributeBody;
+use stun::client::{ self, Timers };
+use stun::header::MessageClass;
+use stun::message::Message;
+
+use common::{ free_port, Server };
+
+#[test]
+fn loopback_is_not_behind_nat() {
+    let (port, alternate_port) = (free_port(), free_port());
+    let config = format!("alternate_ip = \"127.0.0.2\"\n\
+                          alternate_port = \"{}\"\n\
+                          [[listen]]\n\
+                          ip = \"127.0.0.1\"\n\
+                          udp_port = {}\n", alternate_port, port);
+    let server = Server::start("natcheck", &config, &[]);
+    let address = SocketAddr::from(([127, 0, 0, 1], port));
+
+    // Retransmitted until the server is up
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client::binding_udp(&socket, address).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_stun-natcheck"))
+        .arg(address.to_string())
+        .arg("127.0.0.1:0")
+        .output()
+        .unwrap();
+    let output = String::from_utf8_lossy(&output.stdout);
+    for line in ["behind nat: no", "mapping: endpoint-independent", "filtering: endpoint-independent", "hairpinning: supported"] {
+        assert!(output.lines().any(|output_line| output_line == line), "no \"{}\" in:\n{}", line, output);
+    }
+
+    let stderr = server.stop();
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
+
+// Attributes of a response, by type code
+fn decode(response:&[u8]) -> (MessageClass, Vec<(u16, Vec<u8>)>) {
+    let response = Message::decode(response).unwrap();
+    let attributes = response.attributes().iter().map(|attribute| (attribute.type_code, attribute.value.to_vec())).collect();
+    (response.header.mclass, attributes)
+}
+
+#[test]
+fn other_listeners_do_not_do_discovery() {
+    let (port, alternate_port, other_port) = (free_port(), free_port(), free_port());
+    let config = format!("alternate_ip = \"127.0.0.2\"\n\
+                          alternate_port = \"{}\"\n\
+                          [[listen]]\n\
+                          ip = \"127.0.0.1\"\n\
+                          udp_port = {}\n\
+                          [[listen]]\n\
+                          ip = \"127.0.0.1\"\n\
+                          udp_port = {}\n", alternate_port, port, other_port);
+    let server = Server::start("natcheck-other", &config, &[]);
+    let address = SocketAddr::from(([127, 0, 0, 1], other_port));
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client::binding_udp(&socket, address).unwrap();
+    let timers = Timers {
+        rto: Duration::from_millis(500),
+        rc: 3,
+        rm: 4,
+    };
+
+    // Nothing about the primary and alternate addresses is given out here
+    let request = client::binding_request(&rand::random(), vec![]);
+    let (response, _) = client::send_udp(&socket, address, &request, &timers).unwrap();
+    let (mclass, attributes) = decode(&response);
+    assert!(matches!(mclass, MessageClass::Success));
+    assert!(attributes.iter().all(|(type_code, _)| *type_code != 0x802B && *type_code != 0x802C));
+
+    // Nor can a response be sent from any of them
+    let request = client::binding_request(&rand::random(), vec![AttributeBody::ChangeRequest(ChangeRequest::with_flags(true, true))]);
+    let (response, _) = client::send_udp(&socket, address, &request, &timers).unwrap();
+    let (mclass, attributes) = decode(&response);
+    assert!(matches!(mclass, MessageClass::Error));
+    assert!(attributes.iter().any(|(type_code, value)| *type_code == 0x0009 && value[2..4] == [4, 20]));
+    assert!(attributes.iter().any(|(type_code, value)| *type_code == 0x000A && value[..] == [0x00, 0x03]));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_stun-natcheck"))
+        .arg(address.to_string())
+        .arg("127.0.0.1:0")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let error = String::from_utf8_lossy(&output.stderr);
+    assert!(error.contains("does not support nat behaviour discovery"), "{}", error);
+
+    let stderr = server.stop();
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
+
+#[test]
+fn bad_arguments_are_reported_on_stderr() {
+    for args in [&[][..], &["not an address"][..], &["127.0.0.1:3478", "not an address"][..]] {
+        let output = Command::new(env!("CARGO_BIN_EXE_stun-natcheck"))
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(!output.status.success(), "{:?}", args);
+        assert!(output.stdout.is_empty(), "{:?}", args);
+        assert!(!output.stderr.is_empty(), "{:?}", args);
+    }
+}
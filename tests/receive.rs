@@ -0,0 +1,90 @@
+// The server fed random traffic on every transport, after which it must still answer and
+// must not have panicked
+extern crate openssl;
+extern crate rand;
+extern crate stun;
+
+mod common;
+
+use std::io::Write;
+use std::net::{ SocketAddr, TcpStream, UdpSocket };
+use std::thread;
+use std::time::Duration;
+
+use rand::{ Rng, SeedableRng };
+use rand::rngs::StdRng;
+
+use stun::client;
+
+use common::{ certificate, free_port, random_bytes, random_message, Server, SEED };
+
+const DATAGRAMS: usize = 5000;
+const CONNECTIONS: usize = 100;
+// Record type of a DTLS handshake, which is what starts a DTLS session
+const HANDSHAKE: u8 = 22;
+
+// Random bytes, STUN messages with random attributes, or the start of a DTLS handshake
+fn random_datagram(rng:&mut StdRng) -> Vec<u8> {
+    let mut datagram = match rng.gen_bool(0.5) {
+        true => random_message(rng),
+        false => random_bytes(rng, 200),
+    };
+    if !datagram.is_empty() && rng.gen_bool(0.2) {
+        datagram[0] = HANDSHAKE;
+    }
+    // Often cut short, as a message that lost its tail would be
+    let length = rng.gen_range(0..=datagram.len());
+    datagram.truncate(length.max(datagram.len() / 2));
+    datagram
+}
+
+#[test]
+fn random_traffic_does_not_panic_the_server() {
+    let (port, tls_port) = (free_port(), free_port());
+    let config = format!("tcp_workers = 32\n\
+                          tcp_idle_timeout = 1\n\
+                          tcp_read_timeout = 1\n\
+                          tls_certificate = \"cert.pem\"\n\
+                          tls_private_key = \"key.pem\"\n\
+                          [[listen]]\n\
+                          ip = \"127.0.0.1\"\n\
+                          udp_port = {0}\n\
+                          tcp_port = {0}\n\
+                          tls_port = {1}\n\
+                          dtls_port = {1}\n", port, tls_port);
+    let (certificate, private_key) = certificate();
+    let server = Server::start("receive", &config, &[("cert.pem", certificate), ("key.pem", private_key)]);
+    let address = SocketAddr::from(([127, 0, 0, 1], port));
+    let tls_address = SocketAddr::from(([127, 0, 0, 1], tls_port));
+
+    // Retransmitted until the server is up
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client::binding_udp(&socket, address).unwrap();
+
+    let mut rng = StdRng::seed_from_u64(SEED);
+    for _ in 0..DATAGRAMS {
+        // Each from a client of its own, as one that has started a DTLS handshake gets
+        // everything it sends after fed to it
+        let datagram = random_datagram(&mut rng);
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let _ = client.send_to(&datagram, address);
+        let _ = client.send_to(&datagram, tls_address);
+    }
+    for _ in 0..CONNECTIONS {
+        let mut stream = random_message(&mut rng);
+        stream.append(&mut random_bytes(&mut rng, 200));
+        for address in [address, tls_address] {
+            if let Ok(mut connection) = TcpStream::connect(address) {
+                let _ = connection.write_all(&stream);
+            }
+        }
+    }
+
+    // Connections left waiting on the rest of a message give up their workers
+    thread::sleep(Duration::from_secs(2));
+    client::binding_udp(&socket, address).unwrap();
+    client::binding_tcp(address).unwrap();
+
+    let stderr = server.stop();
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
@@ -0,0 +1,193 @@
+// Test vectors from RFC 5769
+extern crate md5;
+extern crate stun;
+
+use std::net::SocketAddr;
+
+use md5::{ Digest, Md5 };
+
+use stun::attributes::{ self, Attribute, AttributeBody, MessageAttribute };
+use stun::attributes::fingerprint::Fingerprint;
+use stun::attributes::message_integrity::MessageIntegrity;
+use stun::header;
+use stun::message::Message;
+
+// Section 2.1: a Binding request from an ICE agent, with short-term credentials
+const SAMPLE_REQUEST: [u8; 108] = [
+    0x00, 0x01, 0x00, 0x58,
+    0x21, 0x12, 0xa4, 0x42,
+    0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6, 0x86, 0xfa, 0x87, 0xdf, 0xae,
+    // SOFTWARE
+    0x80, 0x22, 0x00, 0x10,
+    0x53, 0x54, 0x55, 0x4e, 0x20, 0x74, 0x65, 0x73, 0x74, 0x20, 0x63, 0x6c, 0x69, 0x65, 0x6e, 0x74,
+    // PRIORITY
+    0x00, 0x24, 0x00, 0x04,
+    0x6e, 0x00, 0x01, 0xff,
+    // ICE-CONTROLLED
+    0x80, 0x29, 0x00, 0x08,
+    0x93, 0x2f, 0xf9, 0xb1, 0x51, 0x26, 0x3b, 0x36,
+    // USERNAME
+    0x00, 0x06, 0x00, 0x09,
+    0x65, 0x76, 0x74, 0x6a, 0x3a, 0x68, 0x36, 0x76, 0x59, 0x20, 0x20, 0x20,
+    // MESSAGE-INTEGRITY
+    0x00, 0x08, 0x00, 0x14,
+    0x9a, 0xea, 0xa7, 0x0c, 0xbf, 0xd8, 0xcb, 0x56, 0x78, 0x1e, 0xf2, 0xb5, 0xb2, 0xd3, 0xf2, 0x49, 0xc1, 0xb5, 0x71, 0xa2,
+    // FINGERPRINT
+    0x80, 0x28, 0x00, 0x04,
+    0xe5, 0x7a, 0x3b, 0xcf,
+];
+
+// Section 2.2: the response to it, with an IPv4 address
+const SAMPLE_IPV4_RESPONSE: [u8; 80] = [
+    0x01, 0x01, 0x00, 0x3c,
+    0x21, 0x12, 0xa4, 0x42,
+    0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6, 0x86, 0xfa, 0x87, 0xdf, 0xae,
+    // SOFTWARE
+    0x80, 0x22, 0x00, 0x0b,
+    0x74, 0x65, 0x73, 0x74, 0x20, 0x76, 0x65, 0x63, 0x74, 0x6f, 0x72, 0x20,
+    // XOR-MAPPED-ADDRESS
+    0x00, 0x20, 0x00, 0x08,
+    0x00, 0x01, 0xa1, 0x47, 0xe1, 0x12, 0xa6, 0x43,
+    // MESSAGE-INTEGRITY
+    0x00, 0x08, 0x00, 0x14,
+    0x2b, 0x91, 0xf5, 0x99, 0xfd, 0x9e, 0x90, 0xc3, 0x8c, 0x74, 0x89, 0xf9, 0x2a, 0xf9, 0xba, 0x53, 0xf0, 0x6b, 0xe7, 0xd7,
+    // FINGERPRINT
+    0x80, 0x28, 0x00, 0x04,
+    0xc0, 0x7d, 0x4c, 0x96,
+];
+
+// Section 2.3: the response to it, with an IPv6 address
+const SAMPLE_IPV6_RESPONSE: [u8; 92] = [
+    0x01, 0x01, 0x00, 0x48,
+    0x21, 0x12, 0xa4, 0x42,
+    0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6, 0x86, 0xfa, 0x87, 0xdf, 0xae,
+    // SOFTWARE
+    0x80, 0x22, 0x00, 0x0b,
+    0x74, 0x65, 0x73, 0x74, 0x20, 0x76, 0x65, 0x63, 0x74, 0x6f, 0x72, 0x20,
+    // XOR-MAPPED-ADDRESS
+    0x00, 0x20, 0x00, 0x14,
+    0x00, 0x02, 0xa1, 0x47,
+    0x01, 0x13, 0xa9, 0xfa, 0xa5, 0xd3, 0xf1, 0x79, 0xbc, 0x25, 0xf4, 0xb5, 0xbe, 0xd2, 0xb9, 0xd9,
+    // MESSAGE-INTEGRITY
+    0x00, 0x08, 0x00, 0x14,
+    0xa3, 0x82, 0x95, 0x4e, 0x4b, 0xe6, 0x7b, 0xf1, 0x17, 0x84, 0xc9, 0x7c, 0x82, 0x92, 0xc2, 0x75, 0xbf, 0xe3, 0xed, 0x41,
+    // FINGERPRINT
+    0x80, 0x28, 0x00, 0x04,
+    0xc8, 0xfb, 0x0b, 0x4c,
+];
+
+// The short-term password every sample but the last is protected with
+const PASSWORD: &[u8] = b"VOkJxbRl1RmTxUk/WvJxBt";
+
+// Section 2.4: a request with long-term credentials
+const LONG_TERM_REQUEST: [u8; 116] = [
+    0x00, 0x01, 0x00, 0x60,
+    0x21, 0x12, 0xa4, 0x42,
+    0x78, 0xad, 0x34, 0x33, 0xc6, 0xad, 0x72, 0xc0, 0x29, 0xda, 0x41, 0x2e,
+    // USERNAME
+    0x00, 0x06, 0x00, 0x12,
+    0xe3, 0x83, 0x9e, 0xe3, 0x83, 0x88, 0xe3, 0x83, 0xaa, 0xe3, 0x83, 0x83, 0xe3, 0x82, 0xaf, 0xe3, 0x82, 0xb9, 0x00, 0x00,
+    // NONCE
+    0x00, 0x15, 0x00, 0x1c,
+    0x66, 0x2f, 0x2f, 0x34, 0x39, 0x39, 0x6b, 0x39, 0x35, 0x34, 0x64, 0x36, 0x4f, 0x4c, 0x33, 0x34,
+    0x6f, 0x4c, 0x39, 0x46, 0x53, 0x54, 0x76, 0x79, 0x36, 0x34, 0x73, 0x41,
+    // REALM
+    0x00, 0x14, 0x00, 0x0b,
+    0x65, 0x78, 0x61, 0x6d, 0x70, 0x6c, 0x65, 0x2e, 0x6f, 0x72, 0x67, 0x00,
+    // MESSAGE-INTEGRITY
+    0x00, 0x08, 0x00, 0x14,
+    0xf6, 0x70, 0x24, 0x65, 0x6d, 0xd6, 0x4a, 0x3e, 0x02, 0xb8, 0xe0, 0x71, 0x2e, 0x85, 0xc9, 0xa2, 0x8c, 0xa8, 0x96, 0x66,
+];
+
+fn message_integrity(message:&Message) -> MessageIntegrity {
+    match message.get(MessageAttribute::MessageIntegrity) {
+        Ok(Some(AttributeBody::MessageIntegrity(integrity))) => integrity,
+        _ => panic!("no MESSAGE-INTEGRITY"),
+    }
+}
+
+// Checks MESSAGE-INTEGRITY and FINGERPRINT both ways, for samples that end in them
+fn verify_short_term(sample:&[u8]) {
+    let message = Message::decode(sample).unwrap();
+    let integrity = message_integrity(&message);
+    assert!(integrity.verify(message.raw(), PASSWORD));
+    assert!(!integrity.verify(message.raw(), b"password"));
+    let fingerprint = match message.get(MessageAttribute::Fingerprint) {
+        Ok(Some(AttributeBody::Fingerprint(fingerprint))) => fingerprint,
+        _ => panic!("no FINGERPRINT"),
+    };
+    assert!(fingerprint.verify(message.raw()));
+
+    let length = sample.len();
+    let integrity = MessageIntegrity::with_message(&sample[0..(length - 32)], PASSWORD);
+    assert_eq!(integrity.serialise().unwrap(), &sample[(length - 28)..(length - 8)]);
+    assert_eq!(Fingerprint::with_message(&sample[0..(length - 8)]).serialise().unwrap(), &sample[(length - 4)..]);
+
+    // Any change to what they cover shows
+    let mut corrupted = sample.to_vec();
+    corrupted[8] ^= 1;
+    let message = Message::decode(&corrupted).unwrap();
+    assert!(!message_integrity(&message).verify(message.raw(), PASSWORD));
+    match message.get(MessageAttribute::Fingerprint) {
+        Ok(Some(AttributeBody::Fingerprint(fingerprint))) => assert!(!fingerprint.verify(message.raw())),
+        _ => panic!("no FINGERPRINT"),
+    }
+}
+
+fn mapped_address(sample:&[u8]) -> Option<SocketAddr> {
+    match Message::decode(sample).unwrap().get(MessageAttribute::XorMappedAddress) {
+        Ok(Some(AttributeBody::XorMappedAddress(address))) => address.address(),
+        _ => None,
+    }
+}
+
+#[test]
+fn sample_request() {
+    verify_short_term(&SAMPLE_REQUEST);
+}
+
+#[test]
+fn sample_ipv4_response() {
+    verify_short_term(&SAMPLE_IPV4_RESPONSE);
+    assert_eq!(mapped_address(&SAMPLE_IPV4_RESPONSE), Some("192.0.2.1:32853".parse().unwrap()));
+}
+
+#[test]
+fn sample_ipv6_response() {
+    verify_short_term(&SAMPLE_IPV6_RESPONSE);
+    assert_eq!(mapped_address(&SAMPLE_IPV6_RESPONSE), Some("[2001:db8:1234:5678:11:2233:4455:6677]:32853".parse().unwrap()));
+}
+
+#[test]
+fn long_term_message_integrity() {
+    let message = Message::decode(&LONG_TERM_REQUEST).unwrap();
+    let (username, realm, nonce) = match (message.get(MessageAttribute::Username), message.get(MessageAttribute::Realm), message.get(MessageAttribute::Nonce)) {
+        (Ok(Some(AttributeBody::Username(username))), Ok(Some(AttributeBody::Realm(realm))), Ok(Some(AttributeBody::Nonce(nonce)))) => (username, realm, nonce),
+        _ => panic!("no USERNAME, REALM or NONCE"),
+    };
+    assert_eq!(username.value(), Some("\u{30DE}\u{30C8}\u{30EA}\u{30C3}\u{30AF}\u{30B9}"));
+    assert_eq!(realm.value(), Some("example.org"));
+    assert_eq!(nonce.value(), Some("f//499k954d6OL34oL9FSTvy64sA"));
+
+    // The password is "The<U+00AD>M<U+00AA>trIX" before SASLprep
+    let mut hasher = Md5::new();
+    hasher.update(format!("{}:example.org:TheMatrIX", username.value().unwrap()).as_bytes());
+    let key = hasher.finalize();
+    let integrity = message_integrity(&message);
+    assert!(integrity.verify(message.raw(), &key));
+    assert!(!integrity.verify(message.raw(), b"TheMatrIX"));
+}
+
+#[test]
+fn sample_request_has_no_unknown_attributes() {
+    let header = header::verify_header(&SAMPLE_REQUEST[0..20]).unwrap();
+    let (attributes, unknown_attributes) = attributes::get_attributes(&SAMPLE_REQUEST[20..], &header).unwrap();
+    assert!(unknown_attributes.is_empty());
+    match attributes.get(MessageAttribute::Priority) {
+        Some(AttributeBody::Priority(priority)) => assert_eq!(priority.value(), Some(0x6e0001ff)),
+        _ => panic!("no PRIORITY"),
+    }
+
+    let message = Message::decode(&SAMPLE_REQUEST).unwrap();
+    assert!(message.unknown_attributes().is_empty());
+}
@@ -0,0 +1,182 @@
+// RFC 6062 TCP allocations, with peer data relayed over a TCP or TLS data connection that
+// only the allocation's owner may bind
+extern crate md5;
+extern crate openssl;
+extern crate rand;
+extern crate stun;
+
+mod common;
+
+use std::io::{ Read, Write };
+use std::net::{ SocketAddr, TcpListener, TcpStream };
+use std::thread;
+use std::time::Duration;
+
+use md5::{ Digest, Md5 };
+use openssl::ssl::{ SslConnector, SslMethod, SslVerifyMode };
+
+use stun::attributes::{ AttributeBody, MessageAttribute };
+use stun::attributes::connection_id::ConnectionId;
+use stun::attributes::nonce::Nonce;
+use stun::attributes::realm::Realm;
+use stun::attributes::requested_transport::{ self, RequestedTransport };
+use stun::attributes::username::Username;
+use stun::attributes::xor_mapped_address;
+use stun::attributes::xor_peer_address::XorPeerAddress;
+use stun::header::{ MessageClass, MessageType };
+use stun::message::{ Message, MessageBuilder };
+
+use common::{ certificate, free_port, Server };
+
+const REALM: &str = "example.org";
+
+trait Stream: Read + Write {}
+impl<S: Read + Write> Stream for S {}
+
+// A user's credentials, with the nonce the server last handed out
+struct Credentials {
+    username: &'static str,
+    key: Vec<u8>,
+    nonce: String,
+}
+impl Credentials {
+    fn new(username:&'static str, password:&str) -> Credentials {
+        Credentials {
+            username,
+            key: Md5::digest(format!("{}:{}:{}", username, REALM, password).as_bytes()).to_vec(),
+            nonce: String::new(),
+        }
+    }
+}
+
+// Reads one whole STUN message off a stream
+fn read_message(stream:&mut dyn Stream) -> Vec<u8> {
+    let mut message = vec![0; 20];
+    stream.read_exact(&mut message).unwrap();
+    let length = u16::from_be_bytes([message[2], message[3]]) as usize;
+    message.resize(20 + length, 0);
+    stream.read_exact(&mut message[20..]).unwrap();
+    message
+}
+
+fn send(stream:&mut dyn Stream, mtype:MessageType, id:&[u8; 12], attributes:Vec<AttributeBody>, credentials:&Credentials) -> Vec<u8> {
+    let request = MessageBuilder::new(mtype, MessageClass::Request)
+        .transaction_id(id)
+        .attribute(AttributeBody::Username(Username::with_value(credentials.username.to_string())))
+        .attribute(AttributeBody::Realm(Realm::with_value(REALM.to_string())))
+        .attribute(AttributeBody::Nonce(Nonce::with_value(credentials.nonce.clone())))
+        .attributes(attributes)
+        .message_integrity(&credentials.key)
+        .build();
+    stream.write_all(&request).unwrap();
+    read_message(stream)
+}
+
+// Sends a request, taking up a new nonce if the server hands one out
+fn request(stream:&mut dyn Stream, mtype:MessageType, attributes:&dyn Fn() -> Vec<AttributeBody>, credentials:&mut Credentials) -> Vec<u8> {
+    let id = rand::random();
+    let response = send(stream, mtype, &id, attributes(), credentials);
+    let nonce = match Message::decode(&response).unwrap().get(MessageAttribute::Nonce).unwrap() {
+        Some(AttributeBody::Nonce(nonce)) => nonce.value().unwrap().to_string(),
+        _ => return response,
+    };
+    credentials.nonce = nonce;
+    send(stream, mtype, &id, attributes(), credentials)
+}
+
+fn peer_address(peer:SocketAddr) -> AttributeBody {
+    let id = rand::random();
+    AttributeBody::XorPeerAddress(XorPeerAddress::with_address(peer, xor_mapped_address::address_key(&peer, &id)).unwrap())
+}
+
+fn success(response:&[u8]) -> bool {
+    matches!(Message::decode(response).unwrap().header.mclass, MessageClass::Success)
+}
+
+fn error_code(response:&[u8]) -> Option<u16> {
+    let response = Message::decode(response).unwrap();
+    let attribute = response.attributes().iter().find(|attribute| attribute.type_code == 0x0009)?;
+    Some(attribute.value[2] as u16 * 100 + attribute.value[3] as u16)
+}
+
+// Runs a TCP allocation over connections made by connect, relaying data to and from a peer
+fn relay(connect:&dyn Fn() -> Box<dyn Stream>) {
+    let mut alice = Credentials::new("alice", "secret");
+    let mut mallory = Credentials::new("mallory", "hunter2");
+    let peer = TcpListener::bind("127.0.0.1:0").unwrap();
+    let peer_addr = peer.local_addr().unwrap();
+
+    let mut control = connect();
+    let response = request(&mut *control, MessageType::Allocate, &|| vec![AttributeBody::RequestedTransport(RequestedTransport::with_protocol(requested_transport::TCP))], &mut alice);
+    assert!(success(&response));
+    assert!(success(&request(&mut *control, MessageType::CreatePermission, &|| vec![peer_address(peer_addr)], &mut alice)));
+    let response = request(&mut *control, MessageType::Connect, &|| vec![peer_address(peer_addr)], &mut alice);
+    let connection_id = match Message::decode(&response).unwrap().get(MessageAttribute::ConnectionId).unwrap() {
+        Some(AttributeBody::ConnectionId(connection_id)) => connection_id.value().unwrap(),
+        _ => panic!("no CONNECTION-ID"),
+    };
+    let (mut peer, _) = peer.accept().unwrap();
+
+    // Someone else's allocation can't be taken over, though the data connection stays usable
+    let mut data = connect();
+    let connection_id = || vec![AttributeBody::ConnectionId(ConnectionId::with_value(connection_id))];
+    let response = request(&mut *data, MessageType::ConnectionBind, &connection_id, &mut mallory);
+    assert_eq!(error_code(&response), Some(441));
+    assert!(success(&request(&mut *data, MessageType::ConnectionBind, &connection_id, &mut alice)));
+
+    data.write_all(b"hello").unwrap();
+    let mut buf = [0; 5];
+    peer.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+    peer.write_all(b"world").unwrap();
+    data.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"world");
+
+    // The peer closing ends the data connection too
+    drop(peer);
+    assert_eq!(data.read(&mut buf).unwrap_or(0), 0);
+}
+
+#[test]
+fn peer_data_is_relayed_over_tcp_and_tls() {
+    let (tcp_port, tls_port) = (free_port(), free_port());
+    let config = format!("realm = \"{}\"\n\
+                          relay_ip = \"127.0.0.1\"\n\
+                          denied_peers = []\n\
+                          tls_certificate = \"cert.pem\"\n\
+                          tls_private_key = \"key.pem\"\n\
+                          [[listen]]\n\
+                          ip = \"127.0.0.1\"\n\
+                          tcp_port = {}\n\
+                          tls_port = {}\n\
+                          [users]\n\
+                          alice = \"secret\"\n\
+                          mallory = \"hunter2\"\n", REALM, tcp_port, tls_port);
+    let (certificate, private_key) = certificate();
+    let server = Server::start("tcp-relay", &config, &[("cert.pem", certificate), ("key.pem", private_key)]);
+    let tcp_address = SocketAddr::from(([127, 0, 0, 1], tcp_port));
+    let tls_address = SocketAddr::from(([127, 0, 0, 1], tls_port));
+
+    // Retried until the server is up
+    let connect_tcp = || -> Box<dyn Stream> {
+        for _ in 0..50 {
+            if let Ok(stream) = TcpStream::connect(tcp_address) {
+                return Box::new(stream);
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        panic!("server not up");
+    };
+    relay(&connect_tcp);
+
+    let connect_tls = || -> Box<dyn Stream> {
+        let mut connector = SslConnector::builder(SslMethod::tls()).unwrap();
+        connector.set_verify(SslVerifyMode::NONE);
+        let stream = TcpStream::connect(tls_address).unwrap();
+        Box::new(connector.build().connect("localhost", stream).unwrap())
+    };
+    relay(&connect_tls);
+
+    let stderr = server.stop();
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}